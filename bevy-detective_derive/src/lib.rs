@@ -39,6 +39,53 @@ pub fn yarn_command(attr: TokenStream, item: TokenStream) -> TokenStream {
                     Box::new(|commands, tokens| {
                         #(#arg_parsers_lets)*
                         #func_invocation
+                        Ok(())
+                    })
+                );
+    };
+
+    let output = quote! {
+        #input
+        #registration
+    };
+
+    TokenStream::from(output)
+}
+
+/// Registers the function in `FUNCTION_REGISTRY` under its name, or the one given as argument.
+/// `visited`, `visited_count`, the built-in functions and those added with
+/// `DialogRunner::register_function` are looked up first, so a function named e.g. `round` or
+/// `string` is never called; use `DialogRunner::register_function` to replace a built-in one.
+#[proc_macro_attribute]
+pub fn yarn_function(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let attr_args = parse_macro_input!(attr as AttributeArgs);
+    let input = parse_macro_input!(item as ItemFn);
+    let function_name_map = get_func_name(&input, &attr_args);
+    let function_name = input.sig.ident.clone();
+    let arg_converters = convert_args(&input);
+    let arity = arg_converters.len();
+
+    let args = (0..arity)
+        .map(|i| format_ident!("arg{}", i))
+        .collect::<Vec<_>>();
+
+    let arg_converter_lets = arg_converters
+        .iter()
+        .enumerate()
+        .map(|(i, converter)| {
+            let arg = format_ident!("arg{}", i);
+            let new_tstream = TokenStream2::from(converter.clone());
+            quote! { let #arg = #new_tstream; }
+        })
+        .collect::<Vec<_>>();
+
+    let registration = quote! {
+                FUNCTION_REGISTRY.lock().unwrap().register(
+                    #function_name_map,
+                    #arity,
+                    Box::new(|args: &[_]| {
+                        #(#arg_converter_lets)*
+                        Ok(::std::convert::From::from(#function_name(#(#args),*)))
                     })
                 );
     };
@@ -59,25 +106,50 @@ fn get_func_name(input: &ItemFn, attr_args: &AttributeArgs) -> String {
     }
 }
 
+/// Every argument but `&mut Commands` is read from the next token, missing or malformed tokens
+/// are reported as the `Err` of the command instead of panicking
 fn parse_args(input: &ItemFn) -> Vec<TokenStream> {
     let mut arg_parsers = Vec::new();
+    let mut index = 0usize;
     for arg in input.sig.inputs.iter() {
         if let FnArg::Typed(PatType { ty, .. }) = arg {
+            let next_token = quote! {
+                tokens.next().ok_or_else(|| format!("argument {} is missing", #index))?
+            };
             match &**ty {
                 Type::Path(tp) if tp.path.is_ident("String") => arg_parsers.push(
                     quote! {
-                    tokens.next().unwrap().to_string()  }
+                    #next_token  }
                     .into(),
                 ),
                 Type::Path(tp) if tp.path.is_ident("i32") => arg_parsers.push(
-                    quote! {
-                    tokens.next().unwrap().parse::<i32>().unwrap()  }
+                    quote! {{
+                    let token = #next_token;
+                    token.parse::<i32>().map_err(|_| format!("{:?} is not an integer", token))?  }}
+                    .into(),
+                ),
+                Type::Path(tp) if tp.path.is_ident("f32") => arg_parsers.push(
+                    quote! {{
+                    let token = #next_token;
+                    token.parse::<f32>().map_err(|_| format!("{:?} is not a number", token))?  }}
+                    .into(),
+                ),
+                // `{expression}` arguments format bools as `True` and `False`
+                Type::Path(tp) if tp.path.is_ident("bool") => arg_parsers.push(
+                    quote! {{
+                    let token = #next_token;
+                    match token.to_lowercase().as_str() {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(format!("{:?} is not a bool", token)),
+                    }  }}
                     .into(),
                 ),
                 Type::Reference(tr) => {
                     if let Type::Path(tp) = &*tr.elem {
                         if tp.path.is_ident("Commands") {
-                            arg_parsers.push(quote! { commands }.into())
+                            arg_parsers.push(quote! { commands }.into());
+                            continue;
                         } else {
                             panic!(
                                 "Unsupported function's argument reference type: {}",
@@ -88,7 +160,45 @@ fn parse_args(input: &ItemFn) -> Vec<TokenStream> {
                 }
                 _ => panic!("Unsupported function's argument type: UNKNOWN"),
             }
+            index += 1;
         }
     }
     arg_parsers
 }
+
+fn convert_args(input: &ItemFn) -> Vec<TokenStream> {
+    let mut arg_converters = Vec::new();
+    for (i, arg) in input.sig.inputs.iter().enumerate() {
+        if let FnArg::Typed(PatType { ty, .. }) = arg {
+            match &**ty {
+                Type::Path(tp) if tp.path.is_ident("String") => arg_converters.push(
+                    quote! {
+                    args[#i].as_string()  }
+                    .into(),
+                ),
+                Type::Path(tp) if tp.path.is_ident("f32") => arg_converters.push(
+                    quote! {
+                    args[#i].as_number()?  }
+                    .into(),
+                ),
+                Type::Path(tp) if tp.path.is_ident("i32") => arg_converters.push(
+                    quote! {
+                    args[#i].as_number()? as i32  }
+                    .into(),
+                ),
+                Type::Path(tp) if tp.path.is_ident("bool") => arg_converters.push(
+                    quote! {
+                    args[#i].as_bool()?  }
+                    .into(),
+                ),
+                Type::Path(tp) if tp.path.is_ident("YarnValue") => arg_converters.push(
+                    quote! {
+                    args[#i].clone()  }
+                    .into(),
+                ),
+                _ => panic!("Unsupported function's argument type: UNKNOWN"),
+            }
+        }
+    }
+    arg_converters
+}
@@ -2,20 +2,50 @@ use std::sync::{Arc, RwLock};
 use bevy::asset::{AssetLoader, AsyncReadExt, BoxedFuture, LoadContext};
 use bevy::asset::io::Reader;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use crate::asset::asset::YarnSpinnerDialogLoaderError::Io;
-use crate::parsing::components::YarnSpinnerNode;
+use crate::parsing::components::{Declarations, EnumDefinitions, YarnSpinnerNode};
+use crate::parsing::markup::MarkupError;
+use crate::parsing::value::YarnValueType;
 use crate::parsing::yarn_spinner_parsing;
 use crate::parsing::yarn_spinner_parsing::Rule;
 
 #[derive(Asset, TypePath, Debug)]
 pub struct YarnSpinnerDialog {
     pub nodes: Vec<Arc<RwLock<YarnSpinnerNode>>>,
+    pub declarations: Declarations,
+    pub enums: EnumDefinitions,
+}
+
+impl YarnSpinnerDialog {
+    /// Nodes listing `tag` in their `tags:` header, in the order of the script
+    pub fn nodes_with_tag(&self, tag: &str) -> Vec<Arc<RwLock<YarnSpinnerNode>>> {
+        self.nodes
+            .iter()
+            .filter(|node| node.read().unwrap().has_tag(tag))
+            .cloned()
+            .collect()
+    }
 }
 
 #[derive(Default)]
 pub struct YarnSpinnerDialogLoader;
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct YarnSpinnerDialogLoaderSettings {
+    /// Speaker of the options which do not name one in the script
+    pub default_option_speaker: Option<String>,
+}
+
+impl Default for YarnSpinnerDialogLoaderSettings {
+    fn default() -> Self {
+        Self {
+            default_option_speaker: Some(String::from("Player")),
+        }
+    }
+}
+
 #[non_exhaustive]
 #[derive(Debug, Error)]
 pub enum YarnSpinnerDialogLoaderError {
@@ -23,25 +53,47 @@ pub enum YarnSpinnerDialogLoaderError {
     Io(#[from] std::io::Error),
     #[error("Parsing error: {0}")]
     ParsingError(pest::error::Error<Rule>),
+    #[error("Invalid node title \"{title}\" on line {line}, titles may only contain letters, numbers, '_', '.' and '-'")]
+    InvalidTitle { title: String, line: usize },
     #[error("Unknown node in jump_line: {0}")]
-    UnknownNode(String)
+    UnknownNode(String),
+    #[error("Node title used more than once, nodes sharing a title need when: headers: {0}")]
+    DuplicateNode(String),
+    #[error("Variable declared more than once: ${0}")]
+    DuplicateDeclaration(String),
+    #[error("Default value of ${variable_name} is {found}, but it is declared as {declared}")]
+    DeclarationTypeMismatch {
+        variable_name: String,
+        declared: YarnValueType,
+        found: YarnValueType,
+    },
+    #[error("{0}")]
+    InvalidMarkup(MarkupError),
+    #[error("Line id used more than once: {0}")]
+    DuplicateLineId(String),
+    #[error("Enum declared more than once: {0}")]
+    DuplicateEnum(String),
+    #[error("Unknown enum case: {enum_name}.{case}")]
+    UnknownEnumCase { enum_name: String, case: String },
+    #[error("Unknown type of ${variable_name}: {type_name}")]
+    UnknownType { variable_name: String, type_name: String },
 }
 
 impl AssetLoader for YarnSpinnerDialogLoader {
     type Asset = YarnSpinnerDialog;
-    type Settings = ();
+    type Settings = YarnSpinnerDialogLoaderSettings;
     type Error = YarnSpinnerDialogLoaderError;
     fn load<'a>(
         &'a self,
         reader: &'a mut Reader,
-        _settings: &'a Self::Settings,
-        _load_context: &'a mut LoadContext,
+        settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
         Box::pin(async {
             let mut file_content = String::new();
             reader.read_to_string(&mut file_content).await?;
-            let nodes = yarn_spinner_parsing::load_from_file(file_content.as_str());
-            nodes.map(|nodes| YarnSpinnerDialog { nodes })
+            let file_name = load_context.path().file_stem().unwrap_or_default().to_string_lossy();
+            yarn_spinner_parsing::load_from_named_file(file_content.as_str(), &file_name, settings)
         })
     }
 
@@ -1,7 +1,12 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
-use bevy::prelude::{Bundle, Component};
+use bevy::prelude::{Bundle, Commands, Component};
 use bevy::time::Timer;
+use serde::{Deserialize, Serialize};
 use crate::parsing::components::{Tag};
+use crate::dialog_runner::runner::execute_command;
+use crate::dialog_runner::dialog_runner_error::DialogRunnerError;
+use crate::parsing::markup::MarkupParseResult;
 
 #[derive(Clone, Debug)]
 pub enum DialogState {
@@ -22,22 +27,59 @@ impl Display for DialogState {
     }
 }
 
+/// Progress of a runner which outlives a single conversation, variables live in the `StateContext`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RunnerSaveState {
+    pub visit_counts: HashMap<String, usize>,
+    /// Content ids of `<<once>>` blocks, options and line group items which were already seen
+    pub seen: HashSet<String>,
+}
+
+/// Inline command of a dialog line, to be executed once `text` is revealed up to `position`
+#[derive(Clone, Debug)]
+pub struct DialogCommand {
+    pub func_name: String,
+    pub args: Vec<String>,
+    /// Character of `text` the command is placed before
+    pub position: usize,
+}
+
+impl DialogCommand {
+    /// Runs the command registered in `COMMAND_REGISTRY`, fails if no command has this name
+    pub fn execute(&self, commands: &mut Commands) -> Result<(), DialogRunnerError> {
+        execute_command(&self.func_name, &self.args, commands)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct DialogOption {
+    pub id: usize,
+    /// Stable id of the option's line, from its `#line:` tag or generated at load time
+    pub line_id: String,
+    pub speaker: Option<String>,
+    /// Plain text of the option, without markup
     pub text: String,
-    pub node: String,
+    /// The option including the speaker's name, with the markup attributes
+    pub markup: MarkupParseResult,
+    pub tags: Vec<Tag>,
     pub used: bool,
 }
 
 #[derive(Clone, Debug, Component)]
 pub enum DialogEvent {
     Dialog {
-        speaker: String,
+        speaker: Option<String>,
+        /// Plain text of the line, without the speaker's name and markup
         text: String,
+        /// The line including the speaker's name, with the markup attributes
+        markup: MarkupParseResult,
+        commands: Vec<DialogCommand>,
         tags: Vec<Tag>,
+        /// Stable id of the line, from its `#line:` tag or generated at load time
+        line_id: String,
     },
     Options {
-        speaker: String,
+        speaker: Option<String>,
         options: Vec<DialogOption>,
     },
     Waiting,
@@ -1,16 +1,18 @@
 use bevy::utils::HashMap;
 
+use crate::parsing::value::YarnValue;
+
 pub trait StateContext {
-    fn get_value(&self, key: &str) -> Option<&bool>;
-    fn set_value(&mut self, key: &str, value: &bool);
+    fn get_value(&self, key: &str) -> Option<YarnValue>;
+    fn set_value(&mut self, key: &str, value: &YarnValue);
 }
 
-impl StateContext for HashMap<String, bool> {
-    fn get_value(&self, key: &str) -> Option<&bool> {
-        self.get(key)
+impl StateContext for HashMap<String, YarnValue> {
+    fn get_value(&self, key: &str) -> Option<YarnValue> {
+        self.get(key).cloned()
     }
 
-    fn set_value(&mut self, key: &str, value: &bool) {
-        self.insert(key.to_string(), *value);
+    fn set_value(&mut self, key: &str, value: &YarnValue) {
+        self.insert(key.to_string(), value.clone());
     }
 }
@@ -1,12 +1,26 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use crate::dialog_runner::components::DialogState;
+use crate::parsing::markup::MarkupError;
+use crate::parsing::value::{YarnValue, YarnValueConversionError, YarnValueType};
 
 #[derive(Debug)]
 pub enum DialogRunnerError {
     StartingNodeNotFound { node_name: String },
     UnknownNodeChosen { node_name: String },
-    WrongState { current: DialogState, expected: DialogState }
+    UnknownJumpTarget { node_name: String },
+    UnknownOptionChosen { option_id: usize },
+    NoEligibleNode { node_name: String },
+    WrongState { current: DialogState, expected: DialogState },
+    UndefinedVariable { variable_name: String },
+    InvalidValue { value: YarnValue, expected: &'static str },
+    VariableTypeMismatch { variable_name: String, expected: YarnValueType, found: YarnValueType },
+    UnknownFunction { name: String },
+    UnknownCommand { name: String },
+    InvalidCommandArguments { name: String, reason: String },
+    FunctionArity { name: String, expected: usize, found: usize },
+    UnknownEnumCase { enum_name: String, case: String },
+    InvalidMarkup { error: MarkupError },
 }
 
 impl Display for DialogRunnerError {
@@ -16,10 +30,46 @@ impl Display for DialogRunnerError {
                 write!(f, "Selected starting node does not exist in this dialog: {}", node_name),
             DialogRunnerError::UnknownNodeChosen { node_name} =>
                 write!(f, "Unknown node chose: {}", node_name),
+            DialogRunnerError::UnknownJumpTarget { node_name } =>
+                write!(f, "No node to jump to is titled: {}", node_name),
+            DialogRunnerError::UnknownOptionChosen { option_id } =>
+                write!(f, "Unknown option chosen: {}", option_id),
+            DialogRunnerError::NoEligibleNode { node_name } =>
+                write!(f, "No node of the node group is eligible: {}", node_name),
             DialogRunnerError::WrongState { current, expected} =>
-                write!(f, "Current state: {}, expected to perform this operation: {}", current, expected)
+                write!(f, "Current state: {}, expected to perform this operation: {}", current, expected),
+            DialogRunnerError::UndefinedVariable { variable_name } =>
+                write!(f, "Variable is not defined: ${}", variable_name),
+            DialogRunnerError::InvalidValue { value, expected } =>
+                write!(f, "Value {} of type {} cannot be used as {}", value, value.type_name(), expected),
+            DialogRunnerError::VariableTypeMismatch { variable_name, expected, found } =>
+                write!(f, "Variable ${} is declared as {}, but {} was assigned", variable_name, expected, found),
+            DialogRunnerError::UnknownFunction { name } =>
+                write!(f, "Unknown function: {}", name),
+            DialogRunnerError::UnknownCommand { name } =>
+                write!(f, "Unknown command: {}", name),
+            DialogRunnerError::InvalidCommandArguments { name, reason } =>
+                write!(f, "Invalid arguments for command {}: {}", name, reason),
+            DialogRunnerError::FunctionArity { name, expected, found } =>
+                write!(f, "Function {} expects {} arguments, got {}", name, expected, found),
+            DialogRunnerError::UnknownEnumCase { enum_name, case } =>
+                write!(f, "Unknown enum case: {}.{}", enum_name, case),
+            DialogRunnerError::InvalidMarkup { error } =>
+                write!(f, "{}", error)
         }
     }
 }
 
-impl Error for DialogRunnerError {}
\ No newline at end of file
+impl Error for DialogRunnerError {}
+
+impl From<YarnValueConversionError> for DialogRunnerError {
+    fn from(error: YarnValueConversionError) -> Self {
+        DialogRunnerError::InvalidValue { value: error.value, expected: error.target }
+    }
+}
+
+impl From<MarkupError> for DialogRunnerError {
+    fn from(error: MarkupError) -> Self {
+        DialogRunnerError::InvalidMarkup { error }
+    }
+}
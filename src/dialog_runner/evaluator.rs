@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use crate::dialog_runner::context::StateContext;
+use crate::dialog_runner::dialog_runner_error::DialogRunnerError;
+use crate::dialog_runner::dialog_runner_error::DialogRunnerError::{FunctionArity, UndefinedVariable};
+use crate::dialog_runner::functions::FunctionLibrary;
+use crate::dialog_runner::runner::FUNCTION_REGISTRY;
+use crate::parsing::components::{BinaryOperator, Expression, UnaryOperator};
+use crate::parsing::value::YarnValue;
+
+pub struct ExpressionEvaluator<'a, T: StateContext + ?Sized> {
+    pub context: &'a T,
+    pub functions: &'a FunctionLibrary,
+    pub visit_counts: &'a HashMap<String, usize>,
+}
+
+impl<'a, T: StateContext + ?Sized> ExpressionEvaluator<'a, T> {
+    pub fn evaluate(&self, expression: &Expression) -> Result<YarnValue, DialogRunnerError> {
+        match expression {
+            Expression::Value(value) => Ok(value.clone()),
+            Expression::Variable(variable_name) => self
+                .context
+                .get_value(variable_name)
+                .ok_or(UndefinedVariable { variable_name: variable_name.clone() }),
+            Expression::Unary { operator, operand } => {
+                let operand = self.evaluate(operand)?;
+                match operator {
+                    UnaryOperator::Not => Ok(YarnValue::Bool(!operand.as_bool()?)),
+                    UnaryOperator::Negate => Ok(YarnValue::Number(-operand.as_number()?)),
+                }
+            }
+            Expression::Binary { operator, left, right } => self.evaluate_binary(*operator, left, right),
+            Expression::FunctionCall { name, arguments } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call_function(name, &arguments)
+            }
+        }
+    }
+
+    fn call_function(&self, name: &str, arguments: &[YarnValue]) -> Result<YarnValue, DialogRunnerError> {
+        match name {
+            "visited" | "visited_count" => {
+                if arguments.len() != 1 {
+                    return Err(FunctionArity { name: name.to_string(), expected: 1, found: arguments.len() });
+                }
+                let visit_count = self.visit_counts.get(&arguments[0].as_string()).copied().unwrap_or(0);
+                if name == "visited" {
+                    Ok(YarnValue::Bool(visit_count > 0))
+                } else {
+                    Ok(YarnValue::from(visit_count as i32))
+                }
+            }
+            // the runner's functions shadow the registered ones, built-ins included
+            _ if self.functions.contains(name) => self.functions.call(name, arguments),
+            _ => FUNCTION_REGISTRY.lock().unwrap().call(name, arguments),
+        }
+    }
+
+    /// Fills the `{n}` placeholders of a line with its evaluated expressions, escaped braces are kept
+    pub fn format_text(&self, text: &str, expressions: &[Expression]) -> Result<String, DialogRunnerError> {
+        if expressions.is_empty() {
+            return Ok(text.to_string());
+        }
+
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(open) = rest.find(['{', '\\']) {
+            result.push_str(&rest[..open]);
+            rest = &rest[open..];
+            if rest.starts_with('\\') {
+                let escaped = rest.chars().take(2).map(char::len_utf8).sum();
+                result.push_str(&rest[..escaped]);
+                rest = &rest[escaped..];
+                continue;
+            }
+            let placeholder = rest
+                .find('}')
+                .and_then(|close| rest[1..close].parse::<usize>().ok().map(|index| (index, close)))
+                .filter(|(index, _)| *index < expressions.len());
+            match placeholder {
+                Some((index, close)) => {
+                    result.push_str(&self.evaluate(&expressions[index])?.to_string());
+                    rest = &rest[close + 1..];
+                }
+                None => {
+                    result.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        result.push_str(rest);
+
+        Ok(result)
+    }
+
+    fn evaluate_binary(
+        &self,
+        operator: BinaryOperator,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<YarnValue, DialogRunnerError> {
+        let left = self.evaluate(left)?;
+
+        // `and` and `or` short-circuit, so the right side is only evaluated when it matters
+        match operator {
+            BinaryOperator::And if !left.as_bool()? => return Ok(YarnValue::Bool(false)),
+            BinaryOperator::Or if left.as_bool()? => return Ok(YarnValue::Bool(true)),
+            _ => {}
+        }
+
+        let right = self.evaluate(right)?;
+        let value = match operator {
+            BinaryOperator::And | BinaryOperator::Or => YarnValue::Bool(right.as_bool()?),
+            BinaryOperator::Xor => YarnValue::Bool(left.as_bool()? ^ right.as_bool()?),
+            BinaryOperator::Equal => YarnValue::Bool(left.equals(&right)),
+            BinaryOperator::NotEqual => YarnValue::Bool(!left.equals(&right)),
+            BinaryOperator::Less => YarnValue::Bool(left.as_number()? < right.as_number()?),
+            BinaryOperator::LessOrEqual => YarnValue::Bool(left.as_number()? <= right.as_number()?),
+            BinaryOperator::Greater => YarnValue::Bool(left.as_number()? > right.as_number()?),
+            BinaryOperator::GreaterOrEqual => YarnValue::Bool(left.as_number()? >= right.as_number()?),
+            BinaryOperator::Add => match (&left, &right) {
+                (YarnValue::String(_), _) | (_, YarnValue::String(_)) => {
+                    YarnValue::String(format!("{}{}", left, right))
+                }
+                _ => YarnValue::Number(left.as_number()? + right.as_number()?),
+            },
+            BinaryOperator::Subtract => YarnValue::Number(left.as_number()? - right.as_number()?),
+            BinaryOperator::Multiply => YarnValue::Number(left.as_number()? * right.as_number()?),
+            BinaryOperator::Divide => YarnValue::Number(left.as_number()? / right.as_number()?),
+            BinaryOperator::Modulo => YarnValue::Number(left.as_number()? % right.as_number()?),
+        };
+        Ok(value)
+    }
+}
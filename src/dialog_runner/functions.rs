@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use crate::dialog_runner::dialog_runner_error::DialogRunnerError;
+use crate::dialog_runner::dialog_runner_error::DialogRunnerError::{FunctionArity, UnknownFunction};
+use crate::parsing::value::YarnValue;
+
+pub type YarnFn = Box<dyn Fn(&[YarnValue]) -> Result<YarnValue, DialogRunnerError> + Send + Sync>;
+
+struct YarnFunction {
+    arity: usize,
+    function: YarnFn,
+}
+
+/// Functions callable from expressions, every function declares how many arguments it takes
+#[derive(Default)]
+pub struct FunctionLibrary {
+    functions: HashMap<String, YarnFunction>,
+}
+
+impl FunctionLibrary {
+    pub fn register(&mut self, name: &str, arity: usize, function: YarnFn) {
+        self.functions.insert(name.to_string(), YarnFunction { arity, function });
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    pub fn call(&self, name: &str, arguments: &[YarnValue]) -> Result<YarnValue, DialogRunnerError> {
+        let function = self.functions.get(name).ok_or(UnknownFunction { name: name.to_string() })?;
+        if function.arity != arguments.len() {
+            return Err(FunctionArity {
+                name: name.to_string(),
+                expected: function.arity,
+                found: arguments.len(),
+            });
+        }
+        (function.function)(arguments)
+    }
+
+    /// Yarn Spinner's standard library, `visited` and `visited_count` are provided by the runner
+    pub fn with_builtins() -> Self {
+        let mut library = Self::default();
+        library.register("dice", 1, Box::new(|args| {
+            let sides = args[0].as_number()? as i32;
+            Ok(YarnValue::from(fastrand::i32(1..=sides.max(1))))
+        }));
+        library.register("random", 0, Box::new(|_| Ok(YarnValue::Number(fastrand::f32()))));
+        library.register("random_range", 2, Box::new(|args| {
+            let min = args[0].as_number()? as i32;
+            let max = args[1].as_number()? as i32;
+            Ok(YarnValue::from(fastrand::i32(min.min(max)..=max.max(min))))
+        }));
+        library.register("round", 1, Box::new(|args| Ok(YarnValue::Number(args[0].as_number()?.round()))));
+        library.register("round_places", 2, Box::new(|args| {
+            let factor = 10f32.powi(args[1].as_number()? as i32);
+            Ok(YarnValue::Number((args[0].as_number()? * factor).round() / factor))
+        }));
+        library.register("floor", 1, Box::new(|args| Ok(YarnValue::Number(args[0].as_number()?.floor()))));
+        library.register("ceil", 1, Box::new(|args| Ok(YarnValue::Number(args[0].as_number()?.ceil()))));
+        library.register("inc", 1, Box::new(|args| {
+            let number = args[0].as_number()?;
+            Ok(YarnValue::Number(if number.fract() == 0.0 { number + 1.0 } else { number.ceil() }))
+        }));
+        library.register("dec", 1, Box::new(|args| {
+            let number = args[0].as_number()?;
+            Ok(YarnValue::Number(if number.fract() == 0.0 { number - 1.0 } else { number.floor() }))
+        }));
+        library.register("decimal", 1, Box::new(|args| Ok(YarnValue::Number(args[0].as_number()?.fract()))));
+        library.register("int", 1, Box::new(|args| Ok(YarnValue::Number(args[0].as_number()?.trunc()))));
+        library.register("string", 1, Box::new(|args| Ok(YarnValue::String(args[0].as_string()))));
+        library.register("number", 1, Box::new(|args| Ok(YarnValue::Number(args[0].as_number()?))));
+        library.register("bool", 1, Box::new(|args| Ok(YarnValue::Bool(args[0].as_bool()?))));
+        library
+    }
+}
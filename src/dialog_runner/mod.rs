@@ -2,3 +2,6 @@ pub mod components;
 pub mod context;
 pub mod runner;
 pub mod dialog_runner_error;
+pub mod evaluator;
+pub mod functions;
+pub mod saliency;
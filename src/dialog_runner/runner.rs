@@ -1,221 +1,712 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 use std::sync::{Arc, Mutex, RwLock, Weak};
 
 use bevy::prelude::*;
 use lazy_static::lazy_static;
 
-use crate::dialog_runner::components::{DialogEvent, DialogOption, DialogState};
+use crate::asset::asset::YarnSpinnerDialog;
+use crate::dialog_runner::components::{DialogCommand, DialogEvent, DialogOption, DialogState, RunnerSaveState};
 use crate::dialog_runner::context::StateContext;
 use crate::dialog_runner::dialog_runner_error::DialogRunnerError;
-use crate::dialog_runner::dialog_runner_error::DialogRunnerError::{StartingNodeNotFound, UnknownNodeChosen, WrongState};
-use crate::parsing::components::{ConditionType, LineType, OptionPossibility, YarnSpinnerNode};
+use crate::dialog_runner::dialog_runner_error::DialogRunnerError::{NoEligibleNode, StartingNodeNotFound, InvalidCommandArguments, UnknownCommand, UnknownEnumCase, UnknownJumpTarget, UnknownNodeChosen, UnknownOptionChosen, VariableTypeMismatch, WrongState};
+use crate::dialog_runner::evaluator::ExpressionEvaluator;
+use crate::dialog_runner::functions::{FunctionLibrary, YarnFn};
+use crate::dialog_runner::saliency::{BestLeastRecentlyViewedSaliency, SaliencyCandidate, SaliencyStrategy};
+use crate::parsing::components::{CommandArgument, Declarations, EnumDefinitions, Expression, InlineCommand, JumpTarget, LineType, NodeCondition, OptionPossibility, YarnSpinnerNode};
+use crate::parsing::markup::{parse_markup, MarkupParseResult, MarkupValue, INLINE_COMMAND_ATTRIBUTE, INLINE_COMMAND_INDEX_PROPERTY};
+use crate::parsing::value::YarnValue;
 
-pub type CommandFn = Box<dyn Fn(&mut Commands, &mut dyn Iterator<Item = String>) + Send + Sync>;
+/// Receives the command's arguments as text, `Err` describes arguments that cannot be used
+pub type CommandFn = Box<dyn Fn(&mut Commands, &mut dyn Iterator<Item = String>) -> Result<(), String> + Send + Sync>;
 lazy_static! {
     pub static ref COMMAND_REGISTRY: Mutex<HashMap<String, CommandFn>> = Mutex::new(HashMap::new());
+    /// Functions of `#[yarn_function]`, only called when no runner function has the same name
+    pub static ref FUNCTION_REGISTRY: Mutex<FunctionLibrary> = Mutex::new(FunctionLibrary::default());
+}
+
+/// Runs a command registered in `COMMAND_REGISTRY`
+pub fn execute_command(func_name: &str, args: &[String], commands: &mut Commands) -> Result<(), DialogRunnerError> {
+    let registry = COMMAND_REGISTRY.lock().unwrap();
+    let command = registry.get(func_name).ok_or(UnknownCommand { name: func_name.to_string() })?;
+    command(commands, &mut args.iter().cloned()).map_err(|reason| InvalidCommandArguments {
+        name: func_name.to_string(),
+        reason,
+    })
+}
+
+/// Position of the runner inside nested blocks, the first pointer addresses the node's lines and
+/// every following one the `block` of the line its parent points at, e.g. the branch of an `<<if>>`
+#[derive(Clone, Debug)]
+struct LinePointer {
+    block: usize,
+    line: usize,
+}
+
+/// Where a `<<detour>>` happened, the runner resumes after the detour line once the detoured node returns
+#[derive(Clone, Debug)]
+struct DetourFrame {
+    node: Weak<RwLock<YarnSpinnerNode>>,
+    position: Vec<LinePointer>,
 }
 
 pub struct DialogRunner<T: StateContext> {
     nodes: Vec<Arc<RwLock<YarnSpinnerNode>>>,
     current_node: Weak<RwLock<YarnSpinnerNode>>,
-    current_line_index: usize,
+    position: Vec<LinePointer>,
+    call_stack: Vec<DetourFrame>,
     dialog_state: DialogState,
+    declarations: Declarations,
+    enums: EnumDefinitions,
+    functions: FunctionLibrary,
+    visit_counts: HashMap<String, usize>,
+    seen: HashSet<String>,
+    saliency: Box<dyn SaliencyStrategy>,
+    locale: String,
     _phantom: PhantomData<T>,
 }
 
 impl<T: StateContext> DialogRunner<T> {
-    pub fn create_from_nodes(nodes: Vec<Arc<RwLock<YarnSpinnerNode>>>, start_node_title: &str) -> Result<Self, DialogRunnerError> {
-        let current_node = nodes
-            .iter()
-            .find(|node| node.read().unwrap().title == start_node_title)
-            .ok_or(StartingNodeNotFound { node_name: start_node_title.to_string() })?;
-
-        let weak_current_node = Arc::downgrade(current_node);
-
-        Ok(Self {
+    /// Creates the runner starting at `start_node_title`, for a node group the eligible member is
+    /// picked with the `when:` headers evaluated against `context`.
+    ///
+    /// The runner knows nothing of the dialog's declarations and enums: variables are not seeded,
+    /// `<<set>>` is not checked against the declared types and enum cases are not validated. Use
+    /// `create_from_dialog` for a loaded dialog.
+    pub fn create_from_nodes(
+        nodes: Vec<Arc<RwLock<YarnSpinnerNode>>>,
+        start_node_title: &str,
+        context: &T,
+    ) -> Result<Self, DialogRunnerError> {
+        let mut runner = Self {
             nodes,
-            current_node: weak_current_node,
-            current_line_index: 0,
+            current_node: Weak::new(),
+            position: Self::node_start(),
+            call_stack: vec![],
             dialog_state: DialogState::Start,
+            declarations: Declarations::new(),
+            enums: EnumDefinitions::new(),
+            functions: FunctionLibrary::with_builtins(),
+            visit_counts: HashMap::new(),
+            seen: HashSet::new(),
+            saliency: Box::new(BestLeastRecentlyViewedSaliency::default()),
+            locale: String::from("en"),
             _phantom: PhantomData,
-        })
+        };
+        let start_node = runner
+            .find_node(start_node_title, context)?
+            .ok_or(StartingNodeNotFound { node_name: start_node_title.to_string() })?;
+        runner.enter_node(start_node);
+        Ok(runner)
+    }
+
+    /// Creates the runner for a loaded dialog, declared variables missing from the context are
+    /// seeded with their default values
+    pub fn create_from_dialog(dialog: &YarnSpinnerDialog, start_node_title: &str, context: &mut T) -> Result<Self, DialogRunnerError> {
+        for declaration in dialog.declarations.values() {
+            if context.get_value(&declaration.variable_name).is_none() {
+                context.set_value(&declaration.variable_name, &declaration.default_value);
+            }
+        }
+        let mut runner = Self::create_from_nodes(dialog.nodes.clone(), start_node_title, context)?;
+        runner.declarations = dialog.declarations.clone();
+        runner.enums = dialog.enums.clone();
+        Ok(runner)
     }
 
-    pub fn next_event(&mut self, context: &mut T, commands: &mut Commands) -> DialogEvent {
+    pub fn next_event(&mut self, context: &mut T, commands: &mut Commands) -> Result<DialogEvent, DialogRunnerError> {
         match self.dialog_state {
             DialogState::Start | DialogState::Dialog => self.handle_dialog(context, commands),
-            DialogState::Waiting => DialogEvent::Waiting,
-            DialogState::End => DialogEvent::End,
+            DialogState::Waiting => Ok(DialogEvent::Waiting),
+            DialogState::End => Ok(DialogEvent::End),
         }
     }
 
-    pub fn make_decision(&mut self, decision: &str) -> Result<(), DialogRunnerError> {
+    pub fn make_decision(&mut self, option_id: usize) -> Result<(), DialogRunnerError> {
         if let DialogState::Waiting = self.dialog_state {
-            self.update_used(decision);
-            self.current_node = Arc::downgrade(self
-                .nodes
-                .iter()
-                .find(|node| node.read().unwrap().title == decision)
-                .ok_or(UnknownNodeChosen { node_name: decision.to_string() })?);
-            self.current_line_index = 0;
+            self.update_used(option_id)?;
+            self.position.push(LinePointer { block: option_id, line: 0 });
             self.dialog_state = DialogState::Start;
+            self.leave_finished_blocks();
             Ok(())
         } else {
             Err(WrongState { current: self.dialog_state.clone(), expected: DialogState::Waiting })
         }
     }
 
-    pub fn reset_to(&mut self, node_title: &str) -> Result<(), DialogRunnerError> {
-        self.current_node = Arc::downgrade(self
-            .nodes
-            .iter()
-            .find(|node| node.read().unwrap().title == node_title)
-            .ok_or(UnknownNodeChosen { node_name: node_title.to_string() })?);
-        self.current_line_index = 0;
+    pub fn reset_to(&mut self, node_title: &str, context: &T) -> Result<(), DialogRunnerError> {
+        let node = self
+            .find_node(node_title, context)?
+            .ok_or(UnknownNodeChosen { node_name: node_title.to_string() })?;
+        self.call_stack.clear();
+        self.enter_node(node);
         self.dialog_state = DialogState::Start;
         Ok(())
     }
 
-    fn handle_dialog(&mut self, context: &mut T, commands: &mut Commands) -> DialogEvent {
-        self.update_context(context);
-        self.perform_jump();
-        self.execute_command(commands);
-        self.process_event(context, commands)
+    /// Titles of the nodes waiting for a detour to return, the outermost caller comes first
+    pub fn call_stack(&self) -> Vec<String> {
+        self.call_stack
+            .iter()
+            .filter_map(|frame| frame.node.upgrade())
+            .map(|node| node.read().unwrap().title.clone())
+            .collect()
+    }
+
+    /// Makes a function callable from expressions of this runner, replacing a built-in one with the same name
+    pub fn register_function(&mut self, name: &str, arity: usize, function: YarnFn) {
+        self.functions.register(name, arity, function);
+    }
+
+    /// Replaces the strategy picking lines of line groups, by default the most specific line that
+    /// was shown the longest time ago is picked
+    pub fn set_saliency_strategy(&mut self, saliency: Box<dyn SaliencyStrategy>) {
+        self.saliency = saliency;
+    }
+
+    /// Locale code like `en` or `pl-PL` selecting the plural rules of `[plural]` and `[ordinal]`
+    /// markup, English by default
+    pub fn set_locale(&mut self, locale: &str) {
+        self.locale = locale.to_string();
+    }
+
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Nodes of the node group with the given title whose `when:` conditions currently pass
+    pub fn eligible_nodes(&self, group_title: &str, context: &T) -> Result<Vec<Arc<RwLock<YarnSpinnerNode>>>, DialogRunnerError> {
+        let mut eligible = vec![];
+        for node in self.nodes.iter().filter(|node| node.read().unwrap().title == group_title) {
+            if self.passes_node_conditions(&node.read().unwrap(), context)? {
+                eligible.push(node.clone());
+            }
+        }
+        Ok(eligible)
+    }
+
+    pub fn visit_count(&self, node_title: &str) -> usize {
+        self.visit_counts.get(node_title).copied().unwrap_or(0)
+    }
+
+    /// Visit counts and seen `<<once>>` content, to be stored next to the variables of the context
+    pub fn save_state(&self) -> RunnerSaveState {
+        RunnerSaveState {
+            visit_counts: self.visit_counts.clone(),
+            seen: self.seen.clone(),
+        }
+    }
+
+    pub fn load_state(&mut self, state: RunnerSaveState) {
+        self.visit_counts = state.visit_counts;
+        self.seen = state.seen;
+    }
+
+    fn handle_dialog(&mut self, context: &mut T, commands: &mut Commands) -> Result<DialogEvent, DialogRunnerError> {
+        loop {
+            if let DialogState::End = self.dialog_state {
+                return Ok(DialogEvent::End);
+            }
+            let line = self.current_line();
+            if !self.check_condition(line.condition(), context)? {
+                self.move_pointer();
+                continue;
+            }
+            match &line {
+                LineType::SetLine { .. } => {
+                    self.update_context(&line, context)?;
+                    self.move_pointer();
+                }
+                LineType::CommandLine { .. } => {
+                    self.execute_command_line(&line, context, commands)?;
+                    self.move_pointer();
+                }
+                LineType::JumpLine { .. } => self.perform_jump(&line, context)?,
+                LineType::DetourLine { .. } => self.perform_detour(&line, context)?,
+                LineType::ReturnLine => self.return_from_detour(),
+                LineType::StopLine => {
+                    self.call_stack.clear();
+                    self.dialog_state = DialogState::End;
+                }
+                LineType::IfBlock { .. } => self.enter_block(&line, context)?,
+                LineType::OnceBlock { .. } => self.enter_once_block(&line, context)?,
+                LineType::LineGroup { .. } => self.enter_line_group(&line, context)?,
+                LineType::DeclareLine { .. } | LineType::EnumBlock { .. } => self.move_pointer(),
+                LineType::DialogLine { .. } | LineType::OptionLine { .. } => {
+                    let event = self.line_to_event(&line, context)?;
+                    // like in Yarn Spinner, a group whose options are all unavailable is skipped
+                    if matches!(&event, DialogEvent::Options { options, .. } if options.is_empty()) {
+                        self.move_pointer();
+                        continue;
+                    }
+                    return Ok(self.process_event(event));
+                }
+            }
+        }
     }
 
-    fn update_used(&mut self, decision: &str) {
+    fn update_used(&mut self, option_id: usize) -> Result<(), DialogRunnerError> {
         let node_arc = self.current_node.upgrade().unwrap();
         let mut node = node_arc.write().unwrap();
-        let current_line_mut = &mut node.lines[self.current_line_index];
+        let current_line_mut = Self::resolve_line_mut(&mut node.lines, &self.position);
         if let LineType::OptionLine {
             speaker: _speaker,
             possibilities,
         } = current_line_mut
         {
             let possibility = possibilities
-                .iter_mut()
-                .find(|possibility| possibility.jump_to_node_title == decision)
-                .expect("No possibility found");
+                .get_mut(option_id)
+                .ok_or(UnknownOptionChosen { option_id })?;
             possibility.used = true;
+            if possibility.once {
+                drop(node);
+                self.seen.insert(self.content_id(option_id));
+            }
         }
+        Ok(())
     }
 
-    fn process_event(&mut self, context: &mut T, commands: &mut Commands) -> DialogEvent {
-        let event = self.line_to_event(&self.current_line(), context);
+    fn process_event(&mut self, event: DialogEvent) -> DialogEvent {
         self.dialog_state = Self::event_to_dialog_state(&event);
         self.move_pointer();
-        match self.dialog_state {
-            DialogState::Start => self.next_event(context, commands),
-            DialogState::End => DialogEvent::End,
-            _ => event.unwrap(),
-        }
+        event
     }
 
     fn current_line(&self) -> LineType {
         let current_node = self.current_node.upgrade().unwrap();
-        let current_line = current_node.read().unwrap().lines[self.current_line_index].clone();
+        let current_line = Self::resolve_block(&current_node.read().unwrap().lines, &self.position)
+            [self.position.last().unwrap().line] // safe, position always contains the node pointer
+            .clone();
         current_line
     }
 
-    fn line_to_event(&self, line: &LineType, context: &T) -> Option<DialogEvent> {
-        match line {
+    fn resolve_block<'a>(lines: &'a [LineType], position: &[LinePointer]) -> &'a [LineType] {
+        position.windows(2).fold(lines, |lines, pointers| {
+            lines[pointers[0].line].block(pointers[1].block).unwrap() // safe, only block lines are entered
+        })
+    }
+
+    fn resolve_line_mut<'a>(lines: &'a mut [LineType], position: &[LinePointer]) -> &'a mut LineType {
+        let (pointer, parents) = position.split_last().unwrap(); // safe, position always contains the node pointer
+        let block = parents.iter().zip(position.iter().skip(1)).fold(lines, |lines, (parent, child)| {
+            lines[parent.line].block_mut(child.block).unwrap() // safe, only block lines are entered
+        });
+        &mut block[pointer.line]
+    }
+
+    fn line_to_event(&self, line: &LineType, context: &T) -> Result<DialogEvent, DialogRunnerError> {
+        let event = match line {
             LineType::DialogLine {
                 speaker,
                 text,
+                expressions,
+                commands,
                 tags,
-            } => Some(DialogEvent::Dialog {
-                speaker: speaker.clone(),
-                text: text.clone(),
-                tags: tags.clone(),
-            }),
+                line_id,
+                ..
+            } => {
+                let (text, markup, commands) = self.render_text(text, expressions, speaker.as_deref(), commands, context)?;
+                DialogEvent::Dialog {
+                    speaker: speaker.clone(),
+                    text,
+                    markup,
+                    commands,
+                    tags: tags.clone(),
+                    line_id: line_id.clone(),
+                }
+            }
             LineType::OptionLine {
                 speaker,
                 possibilities,
             } => {
-                let options = possibilities
-                    .iter()
-                    .filter(|&x| self.passes_condition(x, context))
-                    .map(|possibility| DialogOption {
-                        text: possibility.text.clone(),
-                        node: possibility.jump_to_node_title.clone(),
-                        used: possibility.used.clone(),
-                    })
-                    .collect();
-                Some(DialogEvent::Options {
+                let mut options = vec![];
+                for (id, possibility) in possibilities.iter().enumerate() {
+                    if self.passes_condition(possibility, context)? && !(possibility.once && self.was_seen(id)) {
+                        let (text, markup, _) = self.render_text(
+                            &possibility.text,
+                            &possibility.expressions,
+                            possibility.speaker.as_deref(),
+                            &[],
+                            context,
+                        )?;
+                        options.push(DialogOption {
+                            id,
+                            line_id: possibility.line_id.clone(),
+                            speaker: possibility.speaker.clone(),
+                            text,
+                            markup,
+                            tags: possibility.tags.clone(),
+                            used: possibility.used.clone(),
+                        });
+                    }
+                }
+                DialogEvent::Options {
                     speaker: speaker.clone(),
                     options,
-                })
+                }
+            }
+            _ => unreachable!(),
+        };
+        Ok(event)
+    }
+
+    /// Fills in the expressions and parses the markup of a line, returning the plain text without
+    /// the speaker's name, the markup of the whole line and the inline commands placed in the text
+    fn render_text(
+        &self,
+        text: &str,
+        expressions: &[Expression],
+        speaker: Option<&str>,
+        inline_commands: &[InlineCommand],
+        context: &T,
+    ) -> Result<(String, MarkupParseResult, Vec<DialogCommand>), DialogRunnerError> {
+        let mut markup = parse_markup(&self.evaluator(context).format_text(text, expressions)?, speaker, &self.locale)?;
+        let plain = markup.without_character_name();
+        let mut commands = vec![];
+        for attribute in plain.attributes.iter().filter(|attribute| attribute.name == INLINE_COMMAND_ATTRIBUTE) {
+            let command = match attribute.property(INLINE_COMMAND_INDEX_PROPERTY) {
+                Some(MarkupValue::Integer(index)) => inline_commands.get(*index as usize),
+                _ => None,
+            };
+            if let Some(command) = command {
+                commands.push(DialogCommand {
+                    func_name: command.func_name.clone(),
+                    args: self.command_args(&command.args, context)?,
+                    position: attribute.position,
+                });
             }
-            _ => None,
         }
+        markup.attributes.retain(|attribute| attribute.name != INLINE_COMMAND_ATTRIBUTE);
+        Ok((plain.text, markup, commands))
+    }
+
+    fn passes_condition(&self, possibility: &OptionPossibility, context: &T) -> Result<bool, DialogRunnerError> {
+        self.check_condition(possibility.condition.as_ref(), context)
     }
 
-    fn passes_condition(&self, possibility: &OptionPossibility, context: &T) -> bool {
-        match &possibility.condition {
-            Some(condition) => match context.get_value(&condition.variable_name) {
-                Some(value) => match condition.condition {
-                    ConditionType::Equal => value == &condition.value,
-                    ConditionType::NotEqual => value != &condition.value,
-                },
-                None => false,
-            },
-            None => true,
+    fn check_condition(&self, condition: Option<&Expression>, context: &T) -> Result<bool, DialogRunnerError> {
+        match condition {
+            Some(condition) => Ok(self.evaluator(context).evaluate(condition)?.as_bool()?),
+            None => Ok(true),
         }
     }
 
-    fn update_context(&mut self, context: &mut T) {
+    fn update_context(&mut self, line: &LineType, context: &mut T) -> Result<(), DialogRunnerError> {
         if let LineType::SetLine {
             variable_name,
             value,
-        } = &self.current_line()
+            ..
+        } = line
         {
-            context.set_value(variable_name, value);
+            let value = self.evaluator(context).evaluate(value)?;
+            if let YarnValue::Enum { enum_name, case } = &value {
+                if !self.enums.get(enum_name).is_some_and(|definition| definition.has_case(case)) {
+                    return Err(UnknownEnumCase { enum_name: enum_name.clone(), case: case.clone() });
+                }
+            }
+            if let Some(declaration) = self.declarations.get(variable_name) {
+                if declaration.value_type != value.value_type() {
+                    return Err(VariableTypeMismatch {
+                        variable_name: variable_name.clone(),
+                        expected: declaration.value_type.clone(),
+                        found: value.value_type(),
+                    });
+                }
+            }
+            context.set_value(variable_name, &value);
         }
+        Ok(())
     }
 
-    fn perform_jump(&mut self) {
-        if let LineType::JumpLine { node, node_title} = &self.current_line() {
-            self.current_node = node.clone();
-            self.current_line_index = 0;
+    fn perform_jump(&mut self, line: &LineType, context: &T) -> Result<(), DialogRunnerError> {
+        if let LineType::JumpLine { node, target, .. } = line {
+            let target = self.resolve_target(node, target, context)?;
+            self.enter_node(target);
         }
+        Ok(())
     }
 
-    fn execute_command(&mut self, commands: &mut Commands) {
-        if let LineType::CommandLine { func_name, args } = &self.current_line() {
-            COMMAND_REGISTRY.lock().unwrap().get(func_name).unwrap()(
-                commands,
-                &mut args.clone().into_iter(),
-            );
+    /// Enters the target node like a jump, but remembers the current position to return to
+    fn perform_detour(&mut self, line: &LineType, context: &T) -> Result<(), DialogRunnerError> {
+        if let LineType::DetourLine { node, target, .. } = line {
+            let target = self.resolve_target(node, target, context)?;
+            self.call_stack.push(DetourFrame {
+                node: self.current_node.clone(),
+                position: self.position.clone(),
+            });
+            self.enter_node(target);
         }
+        Ok(())
+    }
+
+    /// Resumes after the latest detour, without a detour to return from the dialog ends
+    fn return_from_detour(&mut self) {
+        match self.call_stack.pop() {
+            Some(frame) => {
+                self.current_node = frame.node;
+                self.position = frame.position;
+                self.move_pointer();
+            }
+            None => self.dialog_state = DialogState::End,
+        }
+    }
+
+    /// Node of a jump or detour, targets not resolved when loading are node groups or expressions
+    fn resolve_target(
+        &mut self,
+        node: &Weak<RwLock<YarnSpinnerNode>>,
+        target: &JumpTarget,
+        context: &T,
+    ) -> Result<Weak<RwLock<YarnSpinnerNode>>, DialogRunnerError> {
+        if node.upgrade().is_some() {
+            return Ok(node.clone());
+        }
+        let node_title = match target {
+            JumpTarget::Title(title) => title.clone(),
+            JumpTarget::Expression(expression) => self.evaluator(context).evaluate(expression)?.as_string(),
+        };
+        self.select_node(&node_title, context)
+    }
+
+    /// Node titled `node_title`, members of a node group are picked like a jump into the group would
+    fn find_node(&mut self, node_title: &str, context: &T) -> Result<Option<Weak<RwLock<YarnSpinnerNode>>>, DialogRunnerError> {
+        let is_group = self.nodes.iter().any(|node| {
+            let node = node.read().unwrap();
+            node.title == node_title && node.is_in_group()
+        });
+        if is_group {
+            return self.select_node(node_title, context).map(Some);
+        }
+        Ok(self
+            .nodes
+            .iter()
+            .find(|node| node.read().unwrap().title == node_title)
+            .map(Arc::downgrade))
+    }
+
+    /// Picks the most salient eligible node of a node group, or the node itself when only one has
+    /// this title. Fails if there is none, which only happens for jumps to an `{expression}`.
+    fn select_node(&mut self, group_title: &str, context: &T) -> Result<Weak<RwLock<YarnSpinnerNode>>, DialogRunnerError> {
+        let mut candidates = vec![];
+        let mut group_nodes = vec![];
+        for (index, node) in self.nodes.iter().filter(|node| node.read().unwrap().title == group_title).enumerate() {
+            let node_ref = node.read().unwrap();
+            if self.passes_node_conditions(&node_ref, context)? {
+                let complexity = node_ref
+                    .when
+                    .iter()
+                    .map(|condition| match condition {
+                        NodeCondition::Always => 0,
+                        NodeCondition::Expression(expression) => expression.complexity(),
+                    })
+                    .sum();
+                candidates.push(SaliencyCandidate { id: format!("{}#{}", group_title, index), complexity });
+                group_nodes.push(Arc::downgrade(node));
+            }
+        }
+
+        if group_nodes.is_empty() && !self.nodes.iter().any(|node| node.read().unwrap().title == group_title) {
+            return Err(UnknownJumpTarget { node_name: group_title.to_string() });
+        }
+        let selected = self
+            .saliency
+            .select(&candidates)
+            .ok_or(NoEligibleNode { node_name: group_title.to_string() })?;
+        self.saliency.viewed(&candidates[selected]);
+        Ok(group_nodes.swap_remove(selected))
+    }
+
+    fn passes_node_conditions(&self, node: &YarnSpinnerNode, context: &T) -> Result<bool, DialogRunnerError> {
+        for condition in &node.when {
+            if let NodeCondition::Expression(expression) = condition {
+                if !self.check_condition(Some(expression), context)? {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn enter_node(&mut self, node: Weak<RwLock<YarnSpinnerNode>>) {
+        let title = node.upgrade().unwrap().read().unwrap().title.clone();
+        *self.visit_counts.entry(title).or_insert(0) += 1;
+        self.current_node = node;
+        self.position = Self::node_start();
+    }
+
+    fn evaluator<'a>(&'a self, context: &'a T) -> ExpressionEvaluator<'a, T> {
+        ExpressionEvaluator {
+            context,
+            functions: &self.functions,
+            visit_counts: &self.visit_counts,
+        }
+    }
+
+    fn execute_command_line(&mut self, line: &LineType, context: &T, commands: &mut Commands) -> Result<(), DialogRunnerError> {
+        if let LineType::CommandLine { func_name, args, .. } = line {
+            execute_command(func_name, &self.command_args(args, context)?, commands)?;
+        }
+        Ok(())
+    }
+
+    /// Evaluates the `{expression}` arguments of a command, every argument is passed on as text
+    fn command_args(&self, args: &[CommandArgument], context: &T) -> Result<Vec<String>, DialogRunnerError> {
+        let evaluator = self.evaluator(context);
+        args.iter()
+            .map(|arg| match arg {
+                CommandArgument::Text(text) => Ok(text.clone()),
+                CommandArgument::Expression(expression) => Ok(evaluator.evaluate(expression)?.to_string()),
+            })
+            .collect()
+    }
+
+    fn enter_block(&mut self, line: &LineType, context: &T) -> Result<(), DialogRunnerError> {
+        if let LineType::IfBlock { branches } = line {
+            for (index, branch) in branches.iter().enumerate() {
+                if self.check_condition(branch.condition.as_ref(), context)? {
+                    self.position.push(LinePointer { block: index, line: 0 });
+                    self.leave_finished_blocks();
+                    return Ok(());
+                }
+            }
+        }
+        self.move_pointer();
+        Ok(())
+    }
+
+    fn enter_line_group(&mut self, line: &LineType, context: &T) -> Result<(), DialogRunnerError> {
+        if let LineType::LineGroup { items } = line {
+            let mut candidates = vec![];
+            let mut blocks = vec![];
+            for (index, item) in items.iter().enumerate() {
+                if self.check_condition(item.condition.as_ref(), context)? && !(item.once && self.was_seen(index)) {
+                    // <<once>> counts as one more term of the condition
+                    candidates.push(SaliencyCandidate {
+                        id: self.content_id(index),
+                        complexity: item.condition.as_ref().map_or(0, |condition| condition.complexity())
+                            + item.once as usize,
+                    });
+                    blocks.push(index);
+                }
+            }
+
+            if let Some(selected) = self.saliency.select(&candidates) {
+                self.saliency.viewed(&candidates[selected]);
+                if items[blocks[selected]].once {
+                    self.seen.insert(candidates[selected].id.clone());
+                }
+                self.position.push(LinePointer { block: blocks[selected], line: 0 });
+                self.leave_finished_blocks();
+                return Ok(());
+            }
+        }
+        self.move_pointer();
+        Ok(())
+    }
+
+    /// Runs the `<<once>>` branch the first time it is reached with a passing condition, the
+    /// `<<else>>` branch otherwise
+    fn enter_once_block(&mut self, line: &LineType, context: &T) -> Result<(), DialogRunnerError> {
+        if let LineType::OnceBlock { branches } = line {
+            let block = if !self.was_seen(0) && self.check_condition(branches.first().condition.as_ref(), context)? {
+                self.seen.insert(self.content_id(0));
+                Some(0)
+            } else {
+                (branches.len() > 1).then_some(1)
+            };
+            if let Some(block) = block {
+                self.position.push(LinePointer { block, line: 0 });
+                self.leave_finished_blocks();
+                return Ok(());
+            }
+        }
+        self.move_pointer();
+        Ok(())
+    }
+
+    fn was_seen(&self, block: usize) -> bool {
+        self.seen.contains(&self.content_id(block))
+    }
+
+    /// Identifies the `block` of the current line, stable as long as the node is not edited
+    fn content_id(&self, block: usize) -> String {
+        let path: Vec<String> = self
+            .position
+            .iter()
+            .map(|pointer| format!("{}-{}", pointer.block, pointer.line))
+            .collect();
+        format!("{}:{}:{}", self.node_id(), path.join("."), block)
+    }
+
+    /// Title of the current node, members of a node group are told apart by their position in the group
+    fn node_id(&self) -> String {
+        let current_node = self.current_node.upgrade().unwrap();
+        let node = current_node.read().unwrap();
+        if !node.is_in_group() {
+            return node.title.clone();
+        }
+        let index = self
+            .nodes
+            .iter()
+            .filter(|other| Arc::ptr_eq(other, &current_node) || other.read().unwrap().title == node.title)
+            .position(|other| Arc::ptr_eq(other, &current_node))
+            .unwrap(); // safe, the current node is one of the runner's nodes
+        format!("{}#{}", node.title, index)
     }
 
     fn move_pointer(&mut self) {
         match self.dialog_state {
             DialogState::Waiting => {}
             _ => {
-                self.current_line_index += 1;
-                if self.current_line_index >= self.current_node.upgrade().unwrap().read().unwrap().lines.len() {
-                    self.dialog_state = DialogState::End;
-                }
+                self.position.last_mut().unwrap().line += 1; // safe, position always contains the node pointer
+                self.leave_finished_blocks();
+            }
+        }
+    }
+
+    /// Steps out of every block that has no lines left, when the node itself runs out the latest
+    /// detour returns or, if there is none, the dialog ends
+    fn leave_finished_blocks(&mut self) {
+        while self.current_block_finished() {
+            if self.position.len() > 1 {
+                self.position.pop();
+            } else if let Some(frame) = self.call_stack.pop() {
+                self.current_node = frame.node;
+                self.position = frame.position;
+            } else {
+                self.dialog_state = DialogState::End;
+                return;
             }
+            self.position.last_mut().unwrap().line += 1;
         }
     }
 
-    fn event_to_dialog_state(event: &Option<DialogEvent>) -> DialogState {
+    fn current_block_finished(&self) -> bool {
+        let current_node = self.current_node.upgrade().unwrap();
+        let node = current_node.read().unwrap();
+        self.position.last().unwrap().line >= Self::resolve_block(&node.lines, &self.position).len()
+    }
+
+    fn node_start() -> Vec<LinePointer> {
+        vec![LinePointer { block: 0, line: 0 }]
+    }
+
+    fn event_to_dialog_state(event: &DialogEvent) -> DialogState {
         match event {
-            Some(DialogEvent::Dialog {
+            DialogEvent::Dialog {
                 speaker: _speaker,
                 text: _text,
+                markup: _markup,
+                commands: _commands,
                 tags: _tags,
-            }) => DialogState::Dialog,
-            Some(DialogEvent::Options {
+                line_id: _line_id,
+            } => DialogState::Dialog,
+            DialogEvent::Options {
                 speaker: _speaker,
                 options: _options,
-            }) => DialogState::Waiting,
-            Some(DialogEvent::Waiting) => DialogState::Waiting,
-            Some(DialogEvent::End) => DialogState::End,
-            None => DialogState::Start, // TODO INNY StATE?
+            } => DialogState::Waiting,
+            DialogEvent::Waiting => DialogState::Waiting,
+            DialogEvent::End => DialogState::End,
         }
     }
 
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use fastrand::Rng;
+
+/// Content eligible to be shown, such as a line of a line group
+#[derive(Clone, Debug)]
+pub struct SaliencyCandidate {
+    pub id: String,
+    /// Number of terms in the condition gating the content, conditionless content scores 0
+    pub complexity: usize,
+}
+
+/// Picks which of several eligible pieces of content is shown
+pub trait SaliencyStrategy: Send + Sync {
+    /// Returns the index of the selected candidate, `None` if `candidates` is empty
+    fn select(&mut self, candidates: &[SaliencyCandidate]) -> Option<usize>;
+
+    /// Called with the candidate that was selected and shown
+    fn viewed(&mut self, _candidate: &SaliencyCandidate) {}
+}
+
+pub struct RandomSaliency {
+    rng: Rng,
+}
+
+impl RandomSaliency {
+    pub fn new() -> Self {
+        Self { rng: Rng::new() }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self { rng: Rng::with_seed(seed) }
+    }
+}
+
+impl Default for RandomSaliency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaliencyStrategy for RandomSaliency {
+    fn select(&mut self, candidates: &[SaliencyCandidate]) -> Option<usize> {
+        (!candidates.is_empty()).then(|| self.rng.usize(..candidates.len()))
+    }
+}
+
+/// Tracks when each candidate was last viewed, never viewed candidates go first
+#[derive(Default)]
+struct ViewHistory {
+    last_viewed: HashMap<String, u64>,
+    views: u64,
+}
+
+impl ViewHistory {
+    fn last_viewed(&self, candidate: &SaliencyCandidate) -> u64 {
+        self.last_viewed.get(&candidate.id).copied().unwrap_or(0)
+    }
+
+    fn viewed(&mut self, candidate: &SaliencyCandidate) {
+        self.views += 1;
+        self.last_viewed.insert(candidate.id.clone(), self.views);
+    }
+}
+
+/// Selects the candidate which was shown the longest time ago, ties go to the earliest candidate
+#[derive(Default)]
+pub struct LeastRecentlyViewedSaliency {
+    history: ViewHistory,
+}
+
+impl SaliencyStrategy for LeastRecentlyViewedSaliency {
+    fn select(&mut self, candidates: &[SaliencyCandidate]) -> Option<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(index, candidate)| (self.history.last_viewed(candidate), *index))
+            .map(|(index, _)| index)
+    }
+
+    fn viewed(&mut self, candidate: &SaliencyCandidate) {
+        self.history.viewed(candidate);
+    }
+}
+
+/// Selects the candidate with the most complex condition, ties go to the least recently viewed one
+#[derive(Default)]
+pub struct BestLeastRecentlyViewedSaliency {
+    history: ViewHistory,
+}
+
+impl SaliencyStrategy for BestLeastRecentlyViewedSaliency {
+    fn select(&mut self, candidates: &[SaliencyCandidate]) -> Option<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(index, candidate)| {
+                (usize::MAX - candidate.complexity, self.history.last_viewed(candidate), *index)
+            })
+            .map(|(index, _)| index)
+    }
+
+    fn viewed(&mut self, candidate: &SaliencyCandidate) {
+        self.history.viewed(candidate);
+    }
+}
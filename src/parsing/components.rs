@@ -1,33 +1,132 @@
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 use std::sync::{RwLock, Weak};
+use bevy::utils::HashMap;
 use vec1::Vec1;
 
+use super::value::{YarnValue, YarnValueType};
+
 #[derive(Clone, Debug)]
-pub struct Condition {
-    pub variable_name: String,
-    pub condition: ConditionType,
-    pub value: bool,
+pub enum Expression {
+    Value(YarnValue),
+    Variable(String),
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expression>,
+    },
+    Binary {
+        operator: BinaryOperator,
+        left: Box<Expression>,
+        right: Box<Expression>,
+    },
+    FunctionCall {
+        name: String,
+        arguments: Vec<Expression>,
+    },
 }
 
-#[derive(Clone, Debug)]
-pub enum ConditionType {
+impl Expression {
+    /// Number of terms joined by boolean operators, used to rank conditions by how specific they are
+    pub fn complexity(&self) -> usize {
+        match self {
+            Expression::Unary { operator: UnaryOperator::Not, operand } => operand.complexity(),
+            Expression::Binary { operator: BinaryOperator::And | BinaryOperator::Or | BinaryOperator::Xor, left, right } => {
+                left.complexity() + right.complexity()
+            }
+            _ => 1,
+        }
+    }
+
+    /// Literal values used anywhere inside the expression
+    pub fn literals(&self) -> Vec<&YarnValue> {
+        match self {
+            Expression::Value(value) => vec![value],
+            Expression::Variable(_) => vec![],
+            Expression::Unary { operand, .. } => operand.literals(),
+            Expression::Binary { left, right, .. } => {
+                let mut literals = left.literals();
+                literals.extend(right.literals());
+                literals
+            }
+            Expression::FunctionCall { arguments, .. } => {
+                arguments.iter().flat_map(|argument| argument.literals()).collect()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    Negate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOperator {
+    And,
+    Or,
+    Xor,
     Equal,
-    NotEqual
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
 }
 
-impl FromStr for ConditionType {
+impl FromStr for BinaryOperator {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "==" => Ok(ConditionType::Equal),
-            "!=" => Ok(ConditionType::NotEqual),
+            "&&" | "and" => Ok(BinaryOperator::And),
+            "||" | "or" => Ok(BinaryOperator::Or),
+            "^" | "xor" => Ok(BinaryOperator::Xor),
+            "==" | "is" | "eq" => Ok(BinaryOperator::Equal),
+            "!=" | "neq" => Ok(BinaryOperator::NotEqual),
+            "<" | "lt" => Ok(BinaryOperator::Less),
+            "<=" | "lte" => Ok(BinaryOperator::LessOrEqual),
+            ">" | "gt" => Ok(BinaryOperator::Greater),
+            ">=" | "gte" => Ok(BinaryOperator::GreaterOrEqual),
+            "+" => Ok(BinaryOperator::Add),
+            "-" => Ok(BinaryOperator::Subtract),
+            "*" => Ok(BinaryOperator::Multiply),
+            "/" => Ok(BinaryOperator::Divide),
+            "%" => Ok(BinaryOperator::Modulo),
             _ => Err(())
         }
     }
 }
 
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let sign = match self {
+            BinaryOperator::And => "and",
+            BinaryOperator::Or => "or",
+            BinaryOperator::Xor => "xor",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessOrEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterOrEqual => ">=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+        };
+        write!(f, "{}", sign)
+    }
+}
 
+/// Name of the tag identifying a line, e.g. `#line:intro_greeting`
+pub const LINE_ID_TAG: &str = "line";
 
 #[derive(Clone, Debug)]
 pub struct Tag {
@@ -35,42 +134,266 @@ pub struct Tag {
     pub value: String,
 }
 
+/// Argument of a command, `{expression}` arguments are evaluated when the command runs
+#[derive(Clone, Debug)]
+pub enum CommandArgument {
+    /// Bare token or the unescaped content of a quoted string
+    Text(String),
+    Expression(Expression),
+}
+
+/// Command written inside a dialog line, e.g. `Well... <<shrug>> I guess so`
+#[derive(Clone, Debug)]
+pub struct InlineCommand {
+    pub func_name: String,
+    pub args: Vec<CommandArgument>,
+}
+
 #[derive(Clone, Debug)]
 pub struct OptionPossibility {
+    pub speaker: Option<String>,
     pub text: String,
-    pub jump_to_node_title: String,
-    pub jump_to_node: Weak<RwLock<YarnSpinnerNode>>,
-    pub condition: Option<Condition>,
+    /// Value of the `#line:` tag including the `line:` prefix, or an id generated at load time
+    pub line_id: String,
+    pub expressions: Vec<Expression>,
+    pub tags: Vec<Tag>,
+    pub condition: Option<Expression>,
+    /// Marked with `<<once>>`, the option is offered until it was chosen once
+    pub once: bool,
+    pub body: Vec<LineType>,
     pub used: bool,
 }
 
+#[derive(Clone, Debug)]
+pub struct Declaration {
+    pub variable_name: String,
+    pub value_type: YarnValueType,
+    pub default_value: YarnValue,
+}
+
+pub type Declarations = HashMap<String, Declaration>;
+
+#[derive(Clone, Debug)]
+pub struct EnumDefinition {
+    pub name: String,
+    pub cases: Vec<String>,
+}
+
+impl EnumDefinition {
+    pub fn has_case(&self, case: &str) -> bool {
+        self.cases.iter().any(|known| known == case)
+    }
+}
+
+pub type EnumDefinitions = HashMap<String, EnumDefinition>;
+
+#[derive(Clone, Debug)]
+pub struct IfBranch {
+    pub condition: Option<Expression>,
+    pub lines: Vec<LineType>,
+}
+
+/// Alternative of a line group, `lines` start with the dialog line followed by its indented body
+#[derive(Clone, Debug)]
+pub struct LineGroupItem {
+    pub condition: Option<Expression>,
+    /// Marked with `<<once>>`, the item is only shown the first time it is selected
+    pub once: bool,
+    pub lines: Vec<LineType>,
+}
+
+/// Node a `<<jump>>` or `<<detour>>` leads to, only literal titles are resolved when loading
+#[derive(Clone, Debug)]
+pub enum JumpTarget {
+    Title(String),
+    /// `{expression}` evaluated to the node title when the line is reached
+    Expression(Expression),
+}
+
 #[derive(Clone, Debug)]
 pub enum LineType {
     SetLine {
         variable_name: String,
-        value: bool,
+        value: Expression,
+        /// Trailing `<<if>>`, the line is skipped when it fails
+        condition: Option<Expression>,
     },
     CommandLine {
         func_name: String,
-        args: Vec<String>,
+        args: Vec<CommandArgument>,
+        condition: Option<Expression>,
     },
     DialogLine {
-        speaker: String,
+        speaker: Option<String>,
         text: String,
+        expressions: Vec<Expression>,
+        /// Inline commands, their position is kept in `text` as self-closing markup
+        commands: Vec<InlineCommand>,
         tags: Vec<Tag>,
+        /// Value of the `#line:` tag including the `line:` prefix, or an id generated at load time
+        line_id: String,
+        condition: Option<Expression>,
     },
     JumpLine {
-        node_title: String,
-        node: Weak<RwLock<YarnSpinnerNode>>
+        target: JumpTarget,
+        node: Weak<RwLock<YarnSpinnerNode>>,
+        condition: Option<Expression>,
+    },
+    DetourLine {
+        target: JumpTarget,
+        node: Weak<RwLock<YarnSpinnerNode>>,
+        condition: Option<Expression>,
     },
+    ReturnLine,
+    StopLine,
     OptionLine {
-        speaker: String,
+        speaker: Option<String>,
         possibilities: Vec1<OptionPossibility>,
     },
+    IfBlock {
+        branches: Vec1<IfBranch>,
+    },
+    /// `<<once>>` block, the first branch runs the first time the block is reached and its
+    /// condition passes, the optional second branch is the `<<else>>` taken every other time
+    OnceBlock {
+        branches: Vec1<IfBranch>,
+    },
+    DeclareLine {
+        declaration: Declaration,
+    },
+    EnumBlock {
+        definition: EnumDefinition,
+    },
+    LineGroup {
+        items: Vec1<LineGroupItem>,
+    },
+}
+
+impl LineType {
+    /// Lines nested inside this line, `block` selects e.g. the branch of an `<<if>>` block
+    pub fn block(&self, block: usize) -> Option<&Vec<LineType>> {
+        match self {
+            LineType::IfBlock { branches } | LineType::OnceBlock { branches } => {
+                branches.get(block).map(|branch| &branch.lines)
+            }
+            LineType::OptionLine { possibilities, .. } => possibilities.get(block).map(|possibility| &possibility.body),
+            LineType::LineGroup { items } => items.get(block).map(|item| &item.lines),
+            _ => None,
+        }
+    }
+
+    pub fn blocks(&self) -> Vec<&Vec<LineType>> {
+        match self {
+            LineType::IfBlock { branches } | LineType::OnceBlock { branches } => {
+                branches.iter().map(|branch| &branch.lines).collect()
+            }
+            LineType::OptionLine { possibilities, .. } => {
+                possibilities.iter().map(|possibility| &possibility.body).collect()
+            }
+            LineType::LineGroup { items } => items.iter().map(|item| &item.lines).collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn block_mut(&mut self, block: usize) -> Option<&mut Vec<LineType>> {
+        match self {
+            LineType::IfBlock { branches } | LineType::OnceBlock { branches } => {
+                branches.get_mut(block).map(|branch| &mut branch.lines)
+            }
+            LineType::OptionLine { possibilities, .. } => {
+                possibilities.get_mut(block).map(|possibility| &mut possibility.body)
+            }
+            LineType::LineGroup { items } => items.get_mut(block).map(|item| &mut item.lines),
+            _ => None,
+        }
+    }
+
+    /// Trailing `<<if>>` of a dialog, command, jump, detour or set line
+    pub fn condition(&self) -> Option<&Expression> {
+        match self {
+            LineType::SetLine { condition, .. }
+            | LineType::CommandLine { condition, .. }
+            | LineType::DialogLine { condition, .. }
+            | LineType::JumpLine { condition, .. }
+            | LineType::DetourLine { condition, .. } => condition.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Expressions of this line itself, the lines of its blocks are not included
+    pub fn expressions(&self) -> Vec<&Expression> {
+        let mut expressions = match self {
+            LineType::SetLine { value, .. } => vec![value],
+            LineType::CommandLine { args, .. } => argument_expressions(args).collect(),
+            LineType::DialogLine { expressions, commands, .. } => expressions
+                .iter()
+                .chain(commands.iter().flat_map(|command| argument_expressions(&command.args)))
+                .collect(),
+            LineType::JumpLine { target: JumpTarget::Expression(expression), .. }
+            | LineType::DetourLine { target: JumpTarget::Expression(expression), .. } => vec![expression],
+            LineType::OptionLine { possibilities, .. } => possibilities
+                .iter()
+                .flat_map(|possibility| possibility.expressions.iter().chain(possibility.condition.as_ref()))
+                .collect(),
+            LineType::IfBlock { branches } | LineType::OnceBlock { branches } => {
+                branches.iter().filter_map(|branch| branch.condition.as_ref()).collect()
+            }
+            LineType::LineGroup { items } => items.iter().filter_map(|item| item.condition.as_ref()).collect(),
+            _ => vec![],
+        };
+        expressions.extend(self.condition());
+        expressions
+    }
+
+    pub fn blocks_mut(&mut self) -> Vec<&mut Vec<LineType>> {
+        match self {
+            LineType::IfBlock { branches } | LineType::OnceBlock { branches } => {
+                branches.iter_mut().map(|branch| &mut branch.lines).collect()
+            }
+            LineType::OptionLine { possibilities, .. } => {
+                possibilities.iter_mut().map(|possibility| &mut possibility.body).collect()
+            }
+            LineType::LineGroup { items } => items.iter_mut().map(|item| &mut item.lines).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+fn argument_expressions(args: &[CommandArgument]) -> impl Iterator<Item = &Expression> {
+    args.iter().filter_map(|arg| match arg {
+        CommandArgument::Expression(expression) => Some(expression),
+        CommandArgument::Text(_) => None,
+    })
+}
+
+#[derive(Clone, Debug)]
+pub enum NodeCondition {
+    Always,
+    Expression(Expression),
 }
 
 #[derive(Clone, Debug)]
 pub struct YarnSpinnerNode {
     pub title: String,
+    pub when: Vec<NodeCondition>,
+    /// Headers other than `title:` and `when:`, e.g. `position` or `color`
+    pub headers: HashMap<String, String>,
+    /// Space separated entries of the `tags:` header
+    pub tags: Vec<String>,
     pub lines: Vec1<LineType>,
 }
+
+impl YarnSpinnerNode {
+    /// Nodes with `when:` headers belong to the node group of their title
+    pub fn is_in_group(&self) -> bool {
+        !self.when.is_empty()
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.get(key).map(String::as_str)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|node_tag| node_tag == tag)
+    }
+}
@@ -0,0 +1,445 @@
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use bevy::utils::HashMap;
+use thiserror::Error;
+
+use super::plurals::{cardinal, ordinal, PluralCategory};
+
+/// Name of the attribute added for the speaker in front of a line
+pub const CHARACTER_ATTRIBUTE: &str = "character";
+pub const CHARACTER_NAME_PROPERTY: &str = "name";
+/// Self-closing attribute marking where an inline command of a dialog line is placed, its
+/// `index` property points into the line's commands
+pub const INLINE_COMMAND_ATTRIBUTE: &str = "inline_command";
+pub const INLINE_COMMAND_INDEX_PROPERTY: &str = "index";
+const NO_MARKUP_ATTRIBUTE: &str = "nomarkup";
+const TRIM_WHITESPACE_PROPERTY: &str = "trimwhitespace";
+const VALUE_PROPERTY: &str = "value";
+const OTHER_PROPERTY: &str = "other";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarkupValue {
+    Integer(i32),
+    Float(f32),
+    Bool(bool),
+    String(String),
+}
+
+/// Markup applied to a range of the plain text, positions and lengths count characters
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarkupAttribute {
+    pub name: String,
+    pub position: usize,
+    pub length: usize,
+    pub properties: HashMap<String, MarkupValue>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MarkupParseResult {
+    /// The line without any markup
+    pub text: String,
+    /// Attributes ordered by their position
+    pub attributes: Vec<MarkupAttribute>,
+}
+
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("Invalid markup at character {position} of \"{text}\": {message}")]
+pub struct MarkupError {
+    pub text: String,
+    pub position: usize,
+    pub message: String,
+}
+
+impl Display for MarkupValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarkupValue::Integer(number) => write!(f, "{}", number),
+            MarkupValue::Float(number) => write!(f, "{}", number),
+            MarkupValue::Bool(value) => write!(f, "{}", value),
+            MarkupValue::String(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl MarkupAttribute {
+    pub fn property(&self, name: &str) -> Option<&MarkupValue> {
+        self.properties.get(name)
+    }
+}
+
+impl MarkupParseResult {
+    pub fn attribute(&self, name: &str) -> Option<&MarkupAttribute> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+
+    /// Text covered by the attribute
+    pub fn text_for(&self, attribute: &MarkupAttribute) -> String {
+        self.text.chars().skip(attribute.position).take(attribute.length).collect()
+    }
+
+    /// Removes the implicit `[character]` attribute together with the speaker's name it covers,
+    /// the remaining attributes are moved accordingly
+    pub fn without_character_name(&self) -> MarkupParseResult {
+        match self.attribute(CHARACTER_ATTRIBUTE) {
+            Some(character) => self.delete_range(character.position, character.length),
+            None => self.clone(),
+        }
+    }
+
+    fn delete_range(&self, start: usize, length: usize) -> MarkupParseResult {
+        let end = start + length;
+        let text = self
+            .text
+            .chars()
+            .enumerate()
+            .filter(|(index, _)| *index < start || *index >= end)
+            .map(|(_, character)| character)
+            .collect();
+
+        let attributes = self
+            .attributes
+            .iter()
+            .filter(|attribute| {
+                attribute.length == 0 || attribute.position < start || attribute.position + attribute.length > end
+            })
+            .map(|attribute| {
+                let attribute_end = attribute.position + attribute.length;
+                let mut moved = attribute.clone();
+                moved.position = if attribute.position >= end {
+                    attribute.position - length
+                } else {
+                    attribute.position.min(start)
+                };
+                let removed = attribute_end.min(end).saturating_sub(attribute.position.max(start));
+                moved.length = attribute.length - removed;
+                moved
+            })
+            .collect();
+
+        MarkupParseResult { text, attributes }
+    }
+}
+
+/// Splits a line into plain text and markup attributes following Yarn Spinner's markup rules:
+/// `[name]...[/name]` and `[name/]` tags with `name=value` properties, `[/]` closing every open
+/// tag, `[nomarkup]` sections, and backslash escapes like `\[` or `\#`. A `speaker` adds the implicit
+/// `[character]` attribute covering the `Speaker: ` prefix of the returned text.
+///
+/// The self-closing `[select]`, `[plural]` and `[ordinal]` markers are replaced by the property
+/// matching their `value`, plural categories follow the rules of `locale`, e.g.
+/// `[plural value=3 one="% apple" other="% apples"/]` becomes "3 apples" with `%` as the value.
+pub fn parse_markup(line: &str, speaker: Option<&str>, locale: &str) -> Result<MarkupParseResult, MarkupError> {
+    let mut parser = MarkupParser {
+        source: line,
+        locale,
+        chars: line.chars().peekable(),
+        consumed: 0,
+        text: String::new(),
+        length: 0,
+        attributes: vec![],
+        open: vec![],
+    };
+
+    if let Some(speaker) = speaker {
+        let prefix = format!("{}: ", speaker);
+        parser.push_text(&prefix);
+        parser.attributes.push(MarkupAttribute {
+            name: CHARACTER_ATTRIBUTE.to_string(),
+            position: 0,
+            length: prefix.chars().count(),
+            properties: HashMap::from([(CHARACTER_NAME_PROPERTY.to_string(), MarkupValue::String(speaker.to_string()))]),
+        });
+    }
+
+    parser.parse()?;
+    parser.attributes.sort_by_key(|attribute| attribute.position);
+    Ok(MarkupParseResult {
+        text: parser.text,
+        attributes: parser.attributes,
+    })
+}
+
+struct MarkupParser<'a> {
+    source: &'a str,
+    locale: &'a str,
+    chars: Peekable<Chars<'a>>,
+    /// Characters of `source` read so far, used for error positions
+    consumed: usize,
+    text: String,
+    /// Characters of `text`
+    length: usize,
+    attributes: Vec<MarkupAttribute>,
+    /// Tags waiting for their closing tag, with the position they started at
+    open: Vec<(String, usize, HashMap<String, MarkupValue>)>,
+}
+
+impl<'a> MarkupParser<'a> {
+    fn parse(&mut self) -> Result<(), MarkupError> {
+        while let Some(character) = self.next() {
+            match character {
+                '\\' => match self.chars.peek() {
+                    Some('[' | ']' | '\\' | '#' | '<' | '>' | '{' | '}' | '/' | ':') => {
+                        let escaped = self.next().unwrap(); // safe, just peeked
+                        self.push_char(escaped);
+                    }
+                    _ => self.push_char('\\'),
+                },
+                '[' => self.parse_tag()?,
+                ']' => return Err(self.error("unexpected ']', escape it as '\\]'")),
+                _ => self.push_char(character),
+            }
+        }
+
+        // tags left open cover the rest of the line
+        while let Some((name, position, properties)) = self.open.pop() {
+            self.close(name, position, properties);
+        }
+        Ok(())
+    }
+
+    fn parse_tag(&mut self) -> Result<(), MarkupError> {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'/') {
+            self.next();
+            self.skip_whitespace();
+            let name = self.parse_name();
+            self.skip_whitespace();
+            self.expect(']')?;
+            return self.close_tag(&name);
+        }
+
+        let name = self.parse_name();
+        if name.is_empty() {
+            return Err(self.error("expected an attribute name"));
+        }
+        let mut properties = HashMap::new();
+        if self.chars.peek() == Some(&'=') {
+            self.next();
+            properties.insert(name.clone(), self.parse_value()?);
+        }
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some(']') => {
+                    self.next();
+                    if name == NO_MARKUP_ATTRIBUTE {
+                        return self.parse_no_markup(properties);
+                    }
+                    self.open.push((name, self.length, properties));
+                    return Ok(());
+                }
+                Some('/') => {
+                    self.next();
+                    self.skip_whitespace();
+                    self.expect(']')?;
+                    return self.self_closing(name, properties);
+                }
+                Some(_) => {
+                    let property = self.parse_name();
+                    if property.is_empty() {
+                        return Err(self.error("expected a property name"));
+                    }
+                    self.skip_whitespace();
+                    self.expect('=')?;
+                    self.skip_whitespace();
+                    let value = self.parse_value()?;
+                    properties.insert(property, value);
+                }
+                None => return Err(self.error("unterminated markup tag")),
+            }
+        }
+    }
+
+    /// Closes the most recently opened tag with the name, `[/]` closes every open tag
+    fn close_tag(&mut self, name: &str) -> Result<(), MarkupError> {
+        if name.is_empty() {
+            while let Some((name, position, properties)) = self.open.pop() {
+                self.close(name, position, properties);
+            }
+            return Ok(());
+        }
+        let index = self
+            .open
+            .iter()
+            .rposition(|(open_name, _, _)| open_name == name)
+            .ok_or_else(|| self.error(&format!("closing tag [/{}] without an opening tag", name)))?;
+        let (name, position, properties) = self.open.remove(index);
+        self.close(name, position, properties);
+        Ok(())
+    }
+
+    fn close(&mut self, name: String, position: usize, properties: HashMap<String, MarkupValue>) {
+        self.attributes.push(MarkupAttribute {
+            name,
+            position,
+            length: self.length - position,
+            properties,
+        });
+    }
+
+    /// Self-closing tags at the start of the line or after whitespace swallow one following
+    /// whitespace character, unless `trimwhitespace=false` is set
+    fn self_closing(&mut self, name: String, properties: HashMap<String, MarkupValue>) -> Result<(), MarkupError> {
+        if let Some(replacement) = self.replacement(&name, &properties)? {
+            self.push_text(&replacement);
+            return Ok(());
+        }
+
+        let trim = properties.get(TRIM_WHITESPACE_PROPERTY) != Some(&MarkupValue::Bool(false));
+        let after_whitespace = self.text.chars().last().is_none_or(char::is_whitespace);
+        if trim && after_whitespace && self.chars.peek().is_some_and(|next| next.is_whitespace()) {
+            self.next();
+        }
+        self.attributes.push(MarkupAttribute {
+            name,
+            position: self.length,
+            length: 0,
+            properties,
+        });
+        Ok(())
+    }
+
+    /// Text replacing a `[select]`, `[plural]` or `[ordinal]` marker, other tags have none
+    fn replacement(&self, name: &str, properties: &HashMap<String, MarkupValue>) -> Result<Option<String>, MarkupError> {
+        let value = match name {
+            "select" | "plural" | "ordinal" => properties
+                .get(VALUE_PROPERTY)
+                .ok_or_else(|| self.error(&format!("[{}] requires a value property", name)))?,
+            _ => return Ok(None),
+        };
+
+        let key = match (name, value) {
+            ("select", MarkupValue::String(text)) => text.clone(),
+            ("select", _) => value.to_string(),
+            (_, MarkupValue::Integer(number)) => self.category(name, *number as f32).name().to_string(),
+            (_, MarkupValue::Float(number)) => self.category(name, *number).name().to_string(),
+            _ => return Err(self.error(&format!("[{}] requires a number value, got {}", name, value))),
+        };
+        let text = properties
+            .get(&key)
+            .or_else(|| properties.get(OTHER_PROPERTY))
+            .ok_or_else(|| self.error(&format!("[{}] has no text for {}", name, key)))?;
+        Ok(Some(text.to_string().replace('%', &value.to_string())))
+    }
+
+    fn category(&self, name: &str, value: f32) -> PluralCategory {
+        match name {
+            "ordinal" => ordinal(self.locale, value),
+            _ => cardinal(self.locale, value),
+        }
+    }
+
+    /// Copies everything up to `[/nomarkup]` without interpreting it
+    fn parse_no_markup(&mut self, properties: HashMap<String, MarkupValue>) -> Result<(), MarkupError> {
+        const CLOSING: &str = "[/nomarkup]";
+        let position = self.length;
+        let start = self.source.len() - self.remaining().len();
+        let end = self.source[start..]
+            .find(CLOSING)
+            .ok_or_else(|| self.error("[nomarkup] without [/nomarkup]"))?;
+        let literal = &self.source[start..start + end];
+        for _ in 0..literal.chars().count() + CLOSING.len() {
+            self.next();
+        }
+        self.push_text(literal);
+        self.close(NO_MARKUP_ATTRIBUTE.to_string(), position, properties);
+        Ok(())
+    }
+
+    fn parse_name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(character) = self.chars.peek().copied() {
+            if !(character.is_alphanumeric() || character == '_' || character == '-') {
+                break;
+            }
+            name.push(character);
+            self.next();
+        }
+        name
+    }
+
+    fn parse_value(&mut self) -> Result<MarkupValue, MarkupError> {
+        if self.chars.peek() == Some(&'"') {
+            self.next();
+            let mut value = String::new();
+            loop {
+                match self.next() {
+                    Some('\\') => match self.next() {
+                        Some(escaped) => value.push(escaped),
+                        None => return Err(self.error("unterminated string")),
+                    },
+                    Some('"') => return Ok(MarkupValue::String(value)),
+                    Some(character) => value.push(character),
+                    None => return Err(self.error("unterminated string")),
+                }
+            }
+        }
+
+        let mut value = String::new();
+        while let Some(character) = self.chars.peek().copied() {
+            if character.is_whitespace() || character == ']' || character == '/' {
+                break;
+            }
+            value.push(character);
+            self.next();
+        }
+        if value.is_empty() {
+            return Err(self.error("expected a property value"));
+        }
+        Ok(if let Ok(integer) = value.parse::<i32>() {
+            MarkupValue::Integer(integer)
+        } else if let Ok(float) = value.parse::<f32>() {
+            MarkupValue::Float(float)
+        } else if let Ok(boolean) = value.parse::<bool>() {
+            MarkupValue::Bool(boolean)
+        } else {
+            MarkupValue::String(value)
+        })
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), MarkupError> {
+        match self.next() {
+            Some(character) if character == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|character| character.is_whitespace()) {
+            self.next();
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let character = self.chars.next();
+        if character.is_some() {
+            self.consumed += 1;
+        }
+        character
+    }
+
+    fn remaining(&self) -> &'a str {
+        let consumed_bytes: usize = self.source.chars().take(self.consumed).map(char::len_utf8).sum();
+        &self.source[consumed_bytes..]
+    }
+
+    fn push_char(&mut self, character: char) {
+        self.text.push(character);
+        self.length += 1;
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.length += text.chars().count();
+    }
+
+    fn error(&self, message: &str) -> MarkupError {
+        MarkupError {
+            text: self.source.to_string(),
+            position: self.consumed,
+            message: message.to_string(),
+        }
+    }
+}
@@ -1,2 +1,5 @@
 pub mod components;
+pub mod markup;
+pub mod plurals;
+pub mod value;
 pub mod yarn_spinner_parsing;
@@ -0,0 +1,121 @@
+/// CLDR plural category, named like the properties of `[plural]` and `[ordinal]` markers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PluralCategory {
+    Zero,
+    One,
+    Two,
+    Few,
+    Many,
+    Other,
+}
+
+impl PluralCategory {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PluralCategory::Zero => "zero",
+            PluralCategory::One => "one",
+            PluralCategory::Two => "two",
+            PluralCategory::Few => "few",
+            PluralCategory::Many => "many",
+            PluralCategory::Other => "other",
+        }
+    }
+}
+
+/// Operands of the CLDR rules: the integer part of the absolute value and whether the value
+/// has visible fraction digits
+struct Operands {
+    i: u64,
+    fraction: bool,
+}
+
+impl Operands {
+    fn new(value: f32) -> Self {
+        let n = value.abs();
+        Self {
+            i: n.trunc() as u64,
+            fraction: n.fract() != 0.0,
+        }
+    }
+}
+
+/// Language part of a locale code such as `de-AT` or `pt_BR`
+fn language(locale: &str) -> String {
+    locale.split(['-', '_']).next().unwrap_or_default().to_lowercase()
+}
+
+/// Category of a cardinal number, e.g. "1 apple" and "2 apples". Supports en, de, fr, pl, ru
+/// and ja, other locales use the English rules.
+pub fn cardinal(locale: &str, value: f32) -> PluralCategory {
+    let Operands { i, fraction } = Operands::new(value);
+    match language(locale).as_str() {
+        "ja" => PluralCategory::Other,
+        "fr" => {
+            if i <= 1 {
+                PluralCategory::One
+            } else if !fraction && i % 1_000_000 == 0 {
+                PluralCategory::Many
+            } else {
+                PluralCategory::Other
+            }
+        }
+        "pl" => {
+            if fraction {
+                PluralCategory::Other
+            } else if i == 1 {
+                PluralCategory::One
+            } else if (2..=4).contains(&(i % 10)) && !(12..=14).contains(&(i % 100)) {
+                PluralCategory::Few
+            } else {
+                PluralCategory::Many
+            }
+        }
+        "ru" => {
+            if fraction {
+                PluralCategory::Other
+            } else if i % 10 == 1 && i % 100 != 11 {
+                PluralCategory::One
+            } else if (2..=4).contains(&(i % 10)) && !(12..=14).contains(&(i % 100)) {
+                PluralCategory::Few
+            } else {
+                PluralCategory::Many
+            }
+        }
+        _ => {
+            if i == 1 && !fraction {
+                PluralCategory::One
+            } else {
+                PluralCategory::Other
+            }
+        }
+    }
+}
+
+/// Category of an ordinal number, e.g. "1st" and "2nd". Supports en, de, fr, pl, ru and ja,
+/// other locales use the English rules.
+pub fn ordinal(locale: &str, value: f32) -> PluralCategory {
+    let Operands { i, fraction } = Operands::new(value);
+    match language(locale).as_str() {
+        "de" | "pl" | "ru" | "ja" => PluralCategory::Other,
+        "fr" => {
+            if i == 1 && !fraction {
+                PluralCategory::One
+            } else {
+                PluralCategory::Other
+            }
+        }
+        _ => {
+            if fraction {
+                PluralCategory::Other
+            } else if i % 10 == 1 && i % 100 != 11 {
+                PluralCategory::One
+            } else if i % 10 == 2 && i % 100 != 12 {
+                PluralCategory::Two
+            } else if i % 10 == 3 && i % 100 != 13 {
+                PluralCategory::Few
+            } else {
+                PluralCategory::Other
+            }
+        }
+    }
+}
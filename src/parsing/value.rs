@@ -0,0 +1,215 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum YarnValue {
+    Number(f32),
+    String(String),
+    Bool(bool),
+    /// Case of an `<<enum>>` declared in the dialog, e.g. `Mood.Happy`
+    Enum { enum_name: String, case: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum YarnValueType {
+    Number,
+    String,
+    Bool,
+    Enum(String),
+}
+
+/// Rust enum mirroring an `<<enum>>` of the dialog, lets `StateContext` implementations store
+/// typed values instead of case names
+pub trait YarnEnum: Sized {
+    /// Name of the enum in the dialog
+    const ENUM_NAME: &'static str;
+
+    fn case_name(&self) -> &'static str;
+
+    fn from_case_name(case: &str) -> Option<Self>;
+}
+
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("Cannot convert {value:?} to {target}")]
+pub struct YarnValueConversionError {
+    pub value: YarnValue,
+    pub target: &'static str,
+}
+
+impl YarnValue {
+    pub fn type_name(&self) -> String {
+        self.value_type().name().to_string()
+    }
+
+    pub fn value_type(&self) -> YarnValueType {
+        match self {
+            YarnValue::Number(_) => YarnValueType::Number,
+            YarnValue::String(_) => YarnValueType::String,
+            YarnValue::Bool(_) => YarnValueType::Bool,
+            YarnValue::Enum { enum_name, .. } => YarnValueType::Enum(enum_name.clone()),
+        }
+    }
+
+    pub fn from_enum<E: YarnEnum>(value: &E) -> Self {
+        YarnValue::Enum {
+            enum_name: E::ENUM_NAME.to_string(),
+            case: value.case_name().to_string(),
+        }
+    }
+
+    pub fn as_enum<E: YarnEnum>(&self) -> Result<E, YarnValueConversionError> {
+        match self {
+            YarnValue::Enum { enum_name, case } if enum_name == E::ENUM_NAME => {
+                E::from_case_name(case).ok_or_else(|| self.conversion_error(E::ENUM_NAME))
+            }
+            _ => Err(self.conversion_error(E::ENUM_NAME)),
+        }
+    }
+
+    pub fn as_number(&self) -> Result<f32, YarnValueConversionError> {
+        match self {
+            YarnValue::Number(number) => Ok(*number),
+            YarnValue::Bool(value) => Ok(if *value { 1.0 } else { 0.0 }),
+            YarnValue::String(text) => text.trim().parse::<f32>().map_err(|_| self.conversion_error("Number")),
+            YarnValue::Enum { .. } => Err(self.conversion_error("Number")),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, YarnValueConversionError> {
+        match self {
+            YarnValue::Bool(value) => Ok(*value),
+            YarnValue::Number(number) => Ok(*number != 0.0 && !number.is_nan()),
+            YarnValue::String(text) => match text.trim().to_lowercase().as_str() {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(self.conversion_error("Bool")),
+            },
+            YarnValue::Enum { .. } => Err(self.conversion_error("Bool")),
+        }
+    }
+
+    pub fn as_string(&self) -> String {
+        self.to_string()
+    }
+
+    /// Yarn Spinner equality: the right-hand side is converted to the type of
+    /// the left-hand side, values that cannot be converted are never equal.
+    /// Enum values only equal the same case of the same enum.
+    pub fn equals(&self, other: &YarnValue) -> bool {
+        match self {
+            YarnValue::Number(number) => other.as_number() == Ok(*number),
+            YarnValue::Bool(value) => other.as_bool() == Ok(*value),
+            YarnValue::String(text) => *text == other.as_string(),
+            YarnValue::Enum { .. } => self == other,
+        }
+    }
+
+    fn conversion_error(&self, target: &'static str) -> YarnValueConversionError {
+        YarnValueConversionError { value: self.clone(), target }
+    }
+}
+
+impl YarnValueType {
+    pub fn name(&self) -> &str {
+        match self {
+            YarnValueType::Number => "Number",
+            YarnValueType::String => "String",
+            YarnValueType::Bool => "Bool",
+            YarnValueType::Enum(enum_name) => enum_name,
+        }
+    }
+}
+
+/// Parses the built-in type names, enum types are only known once the dialog's enums are collected
+impl FromStr for YarnValueType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Number" => Ok(YarnValueType::Number),
+            "String" => Ok(YarnValueType::String),
+            "Bool" => Ok(YarnValueType::Bool),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for YarnValueType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Display for YarnValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            YarnValue::Number(number) => write!(f, "{}", number),
+            YarnValue::String(text) => write!(f, "{}", text),
+            YarnValue::Bool(true) => write!(f, "True"),
+            YarnValue::Bool(false) => write!(f, "False"),
+            YarnValue::Enum { case, .. } => write!(f, "{}", case),
+        }
+    }
+}
+
+impl From<f32> for YarnValue {
+    fn from(value: f32) -> Self {
+        YarnValue::Number(value)
+    }
+}
+
+impl From<i32> for YarnValue {
+    fn from(value: i32) -> Self {
+        YarnValue::Number(value as f32)
+    }
+}
+
+impl From<bool> for YarnValue {
+    fn from(value: bool) -> Self {
+        YarnValue::Bool(value)
+    }
+}
+
+impl From<String> for YarnValue {
+    fn from(value: String) -> Self {
+        YarnValue::String(value)
+    }
+}
+
+impl From<&str> for YarnValue {
+    fn from(value: &str) -> Self {
+        YarnValue::String(value.to_string())
+    }
+}
+
+impl TryFrom<YarnValue> for f32 {
+    type Error = YarnValueConversionError;
+
+    fn try_from(value: YarnValue) -> Result<Self, Self::Error> {
+        value.as_number()
+    }
+}
+
+impl TryFrom<YarnValue> for i32 {
+    type Error = YarnValueConversionError;
+
+    fn try_from(value: YarnValue) -> Result<Self, Self::Error> {
+        value.as_number().map(|number| number as i32)
+    }
+}
+
+impl TryFrom<YarnValue> for bool {
+    type Error = YarnValueConversionError;
+
+    fn try_from(value: YarnValue) -> Result<Self, Self::Error> {
+        value.as_bool()
+    }
+}
+
+impl From<YarnValue> for String {
+    fn from(value: YarnValue) -> Self {
+        value.as_string()
+    }
+}
@@ -1,99 +1,626 @@
 use std::str::FromStr;
 use std::sync::{Arc, RwLock, Weak};
 
-use bevy::utils::HashMap;
-use pest::iterators::Pair;
+use bevy::utils::{HashMap, HashSet};
+use lazy_static::lazy_static;
+use pest::iterators::{Pair, Pairs};
+use pest::pratt_parser::{Assoc, Op, PrattParser};
 use pest::Parser;
 use pest_derive::Parser;
 use vec1::Vec1;
 
-use crate::asset::asset::YarnSpinnerDialogLoaderError;
-use crate::asset::asset::YarnSpinnerDialogLoaderError::{ParsingError, UnknownNode};
+use crate::asset::asset::{YarnSpinnerDialog, YarnSpinnerDialogLoaderError, YarnSpinnerDialogLoaderSettings};
+use crate::asset::asset::YarnSpinnerDialogLoaderError::{DeclarationTypeMismatch, DuplicateDeclaration, DuplicateEnum, DuplicateLineId, DuplicateNode, InvalidMarkup, InvalidTitle, ParsingError, UnknownEnumCase, UnknownNode, UnknownType};
 
 use super::components::*;
+use super::markup::{parse_markup, INLINE_COMMAND_ATTRIBUTE, INLINE_COMMAND_INDEX_PROPERTY};
+use super::value::{YarnValue, YarnValueType};
 
 #[derive(Parser)]
 #[grammar = "assets/grammar/yarnspinner.pest"]
 pub struct YarnSpinnerParser;
 
-pub fn load_from_file(dialog: &str) -> Result<Vec<Arc<RwLock<YarnSpinnerNode>>>, YarnSpinnerDialogLoaderError> {
+lazy_static! {
+    static ref PRATT_PARSER: PrattParser<Rule> = PrattParser::new()
+        .op(Op::infix(Rule::or_operator, Assoc::Left))
+        .op(Op::infix(Rule::xor_operator, Assoc::Left))
+        .op(Op::infix(Rule::and_operator, Assoc::Left))
+        .op(Op::infix(Rule::equal_operator, Assoc::Left) | Op::infix(Rule::not_equal_operator, Assoc::Left))
+        .op(Op::infix(Rule::less_operator, Assoc::Left)
+            | Op::infix(Rule::less_or_equal_operator, Assoc::Left)
+            | Op::infix(Rule::greater_operator, Assoc::Left)
+            | Op::infix(Rule::greater_or_equal_operator, Assoc::Left))
+        .op(Op::infix(Rule::add_operator, Assoc::Left) | Op::infix(Rule::subtract_operator, Assoc::Left))
+        .op(Op::infix(Rule::multiply_operator, Assoc::Left)
+            | Op::infix(Rule::divide_operator, Assoc::Left)
+            | Op::infix(Rule::modulo_operator, Assoc::Left))
+        .op(Op::prefix(Rule::not_operator) | Op::prefix(Rule::negate_operator));
+}
+
+/// Locale used to check markup when loading, `[plural]` markers fall back to their `other` text
+/// in every locale
+const MARKUP_CHECK_LOCALE: &str = "en";
+
+pub fn load_from_file(
+    dialog: &str,
+    settings: &YarnSpinnerDialogLoaderSettings,
+) -> Result<YarnSpinnerDialog, YarnSpinnerDialogLoaderError> {
+    load_from_named_file(dialog, "", settings)
+}
+
+/// Like `load_from_file`, the file name becomes part of the ids generated for lines without a `#line:` tag
+pub fn load_from_named_file(
+    dialog: &str,
+    file_name: &str,
+    settings: &YarnSpinnerDialogLoaderSettings,
+) -> Result<YarnSpinnerDialog, YarnSpinnerDialogLoaderError> {
     let parsed = YarnSpinnerParser::parse(Rule::yarnspinner, dialog)
         .map_err(|errors| ParsingError(errors))?;
 
-    let mut result: HashMap<String, Arc<RwLock<YarnSpinnerNode>>> = parsed.into_iter()
-        .map(parse_section)
-        .map(|node| (node.read().unwrap().title.clone(), node.clone()))
-        .collect();
+    let nodes: Vec<Arc<RwLock<YarnSpinnerNode>>> = parsed.into_iter().map(parse_section).collect::<Result<_, _>>()?;
+
+    // titles shared by several nodes are only allowed for node groups
+    let mut result: HashMap<String, Arc<RwLock<YarnSpinnerNode>>> = HashMap::new();
+    let mut groups: HashSet<String> = HashSet::new();
+    for node in &nodes {
+        let node_ref = node.read().unwrap();
+        if let Some(existing) = result.get(&node_ref.title) {
+            if !existing.read().unwrap().is_in_group() || !node_ref.is_in_group() {
+                return Err(DuplicateNode(node_ref.title.clone()));
+            }
+        } else {
+            result.insert(node_ref.title.clone(), node.clone());
+        }
+        if node_ref.is_in_group() {
+            groups.insert(node_ref.title.clone());
+        }
+    }
 
-    for node in result.values() {
+    let mut declarations = Declarations::new();
+    let mut enums = EnumDefinitions::new();
+    let mut line_ids: HashSet<String> = HashSet::new();
+    let mut group_sizes: HashMap<String, usize> = HashMap::new();
+    for node in &nodes {
         let mut node_mut= node.write().unwrap();
-        for line in &mut node_mut.lines {
-            if let LineType::JumpLine { node_title, node } = line {
-                *node = Arc::downgrade(result.get(node_title).ok_or(UnknownNode(node_title.to_string()))?)
+        // members of a node group are told apart like the runner does, by their index in the group
+        let node_id = if node_mut.is_in_group() {
+            let index = group_sizes.entry(node_mut.title.clone()).or_default();
+            *index += 1;
+            format!("{}#{}", node_mut.title, *index - 1)
+        } else {
+            node_mut.title.clone()
+        };
+        let prefix = match file_name {
+            "" => format!("{}:{}", LINE_ID_TAG, node_id),
+            _ => format!("{}:{}:{}", LINE_ID_TAG, file_name, node_id),
+        };
+        assign_line_ids(&mut node_mut.lines, &prefix, &mut 0, &mut line_ids)?;
+        resolve_jumps(&mut node_mut.lines, &result, &groups)?;
+        apply_option_speaker(&mut node_mut.lines, &settings.default_option_speaker);
+        collect_declarations(&node_mut.lines, &mut declarations)?;
+        collect_enums(&node_mut.lines, &mut enums)?;
+        check_markup(&node_mut.lines)?;
+    }
+
+    // enums may be used before the node declaring them, so types and literals are checked once all are known
+    for declaration in declarations.values() {
+        check_declaration(declaration, &enums)?;
+    }
+    for node in &nodes {
+        let node_ref = node.read().unwrap();
+        for condition in &node_ref.when {
+            if let NodeCondition::Expression(expression) = condition {
+                check_enum_literals(expression, &enums)?;
+            }
+        }
+        check_enum_lines(&node_ref.lines, &enums)?;
+    }
+
+    Ok(YarnSpinnerDialog {
+        nodes,
+        declarations,
+        enums,
+    })
+}
+
+/// Gives every dialog and option line without a `#line:` tag an id made of `prefix` and the
+/// line's index in the node, and checks that no id is used twice
+fn assign_line_ids(
+    lines: &mut [LineType],
+    prefix: &str,
+    index: &mut usize,
+    line_ids: &mut HashSet<String>,
+) -> Result<(), YarnSpinnerDialogLoaderError> {
+    for line in lines.iter_mut() {
+        let mut ids: Vec<&mut String> = match line {
+            LineType::DialogLine { line_id, .. } => vec![line_id],
+            LineType::OptionLine { possibilities, .. } => {
+                possibilities.iter_mut().map(|possibility| &mut possibility.line_id).collect()
+            }
+            _ => vec![],
+        };
+        for line_id in ids.iter_mut() {
+            if line_id.is_empty() {
+                **line_id = format!("{}:{}", prefix, index);
+            }
+            *index += 1;
+            if !line_ids.insert(line_id.to_string()) {
+                return Err(DuplicateLineId(line_id.to_string()));
+            }
+        }
+        for block in line.blocks_mut() {
+            assign_line_ids(block, prefix, index, line_ids)?;
+        }
+    }
+    Ok(())
+}
+
+/// Lines without interpolations already have their final markup, so it is checked when loading
+/// instead of failing once the line is shown
+fn check_markup(lines: &[LineType]) -> Result<(), YarnSpinnerDialogLoaderError> {
+    for line in lines {
+        match line {
+            LineType::DialogLine { speaker, text, expressions, .. } if expressions.is_empty() => {
+                parse_markup(text, speaker.as_deref(), MARKUP_CHECK_LOCALE).map_err(InvalidMarkup)?;
+            }
+            LineType::OptionLine { possibilities, .. } => {
+                for possibility in possibilities.iter().filter(|possibility| possibility.expressions.is_empty()) {
+                    parse_markup(&possibility.text, possibility.speaker.as_deref(), MARKUP_CHECK_LOCALE)
+                        .map_err(InvalidMarkup)?;
+                }
+            }
+            _ => {}
+        }
+        for block in line.blocks() {
+            check_markup(block)?;
+        }
+    }
+    Ok(())
+}
+
+fn collect_enums(lines: &[LineType], enums: &mut EnumDefinitions) -> Result<(), YarnSpinnerDialogLoaderError> {
+    for line in lines {
+        if let LineType::EnumBlock { definition } = line {
+            if enums.contains_key(&definition.name) {
+                return Err(DuplicateEnum(definition.name.clone()));
+            }
+            enums.insert(definition.name.clone(), definition.clone());
+        }
+        for block in line.blocks() {
+            collect_enums(block, enums)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_enum_lines(lines: &[LineType], enums: &EnumDefinitions) -> Result<(), YarnSpinnerDialogLoaderError> {
+    for line in lines {
+        for expression in line.expressions() {
+            check_enum_literals(expression, enums)?;
+        }
+        for block in line.blocks() {
+            check_enum_lines(block, enums)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_enum_literals(expression: &Expression, enums: &EnumDefinitions) -> Result<(), YarnSpinnerDialogLoaderError> {
+    for literal in expression.literals() {
+        check_enum_literal(literal, enums)?;
+    }
+    Ok(())
+}
+
+fn check_enum_literal(value: &YarnValue, enums: &EnumDefinitions) -> Result<(), YarnSpinnerDialogLoaderError> {
+    if let YarnValue::Enum { enum_name, case } = value {
+        if !enums.get(enum_name).is_some_and(|definition| definition.has_case(case)) {
+            return Err(UnknownEnumCase {
+                enum_name: enum_name.clone(),
+                case: case.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn check_declaration(declaration: &Declaration, enums: &EnumDefinitions) -> Result<(), YarnSpinnerDialogLoaderError> {
+    if let YarnValueType::Enum(enum_name) = &declaration.value_type {
+        if !enums.contains_key(enum_name) {
+            return Err(UnknownType {
+                variable_name: declaration.variable_name.clone(),
+                type_name: enum_name.clone(),
+            });
+        }
+    }
+    if declaration.value_type != declaration.default_value.value_type() {
+        return Err(DeclarationTypeMismatch {
+            variable_name: declaration.variable_name.clone(),
+            declared: declaration.value_type.clone(),
+            found: declaration.default_value.value_type(),
+        });
+    }
+    check_enum_literal(&declaration.default_value, enums)
+}
+
+fn collect_declarations(lines: &[LineType], declarations: &mut Declarations) -> Result<(), YarnSpinnerDialogLoaderError> {
+    for line in lines {
+        if let LineType::DeclareLine { declaration } = line {
+            if declarations.contains_key(&declaration.variable_name) {
+                return Err(DuplicateDeclaration(declaration.variable_name.clone()));
+            }
+            declarations.insert(declaration.variable_name.clone(), declaration.clone());
+        }
+        for block in line.blocks() {
+            collect_declarations(block, declarations)?;
+        }
+    }
+    Ok(())
+}
+
+fn resolve_jumps(
+    lines: &mut [LineType],
+    nodes: &HashMap<String, Arc<RwLock<YarnSpinnerNode>>>,
+    groups: &HashSet<String>,
+) -> Result<(), YarnSpinnerDialogLoaderError> {
+    for line in lines {
+        if let LineType::JumpLine { target: JumpTarget::Title(node_title), node, .. }
+        | LineType::DetourLine { target: JumpTarget::Title(node_title), node, .. } = line
+        {
+            let target = nodes.get(node_title).ok_or(UnknownNode(node_title.to_string()))?;
+            // jumps into node groups are resolved when they happen
+            if !groups.contains(node_title) {
+                *node = Arc::downgrade(target)
+            }
+        }
+        for block in line.blocks_mut() {
+            resolve_jumps(block, nodes, groups)?;
+        }
+    }
+    Ok(())
+}
+
+/// Options without an authored speaker are spoken by the default option speaker from the settings
+fn apply_option_speaker(lines: &mut [LineType], default_speaker: &Option<String>) {
+    for line in lines {
+        if let LineType::OptionLine { speaker, possibilities } = line {
+            *speaker = default_speaker.clone();
+            for possibility in possibilities.iter_mut() {
+                if possibility.speaker.is_none() {
+                    possibility.speaker = default_speaker.clone();
+                }
             }
         }
+        for block in line.blocks_mut() {
+            apply_option_speaker(block, default_speaker);
+        }
     }
-    Ok(result.values().map(|x| x.clone()).collect())
 }
 
-fn parse_section(section: Pair<Rule>) -> Arc<RwLock<YarnSpinnerNode>> {
+fn parse_section(section: Pair<Rule>) -> Result<Arc<RwLock<YarnSpinnerNode>>, YarnSpinnerDialogLoaderError> {
     let mut node_title = String::new();
+    let mut when: Vec<NodeCondition> = vec![];
+    let mut headers: HashMap<String, String> = HashMap::new();
     let mut lines = vec![];
 
     if section.as_rule() == Rule::section {
         for field in section.into_inner() {
             match field.as_rule() {
-                Rule::title => node_title = field.as_str().to_string(),
+                Rule::header_title => node_title = parse_header_title(field)?,
+                Rule::when_header => when.push(parse_when_header(field)),
+                Rule::header => {
+                    let (key, value) = parse_header(field);
+                    headers.insert(key, value);
+                }
                 Rule::section_content => parse_section_content(field, &mut lines),
                 _ => unreachable!(),
             }
         }
     }
 
-    Arc::new(RwLock::new(YarnSpinnerNode {
+    let tags = match headers.get("tags") {
+        Some(tags) => tags.split_whitespace().map(String::from).collect(),
+        None => vec![],
+    };
+
+    Ok(Arc::new(RwLock::new(YarnSpinnerNode {
         title: node_title,
+        when,
+        headers,
+        tags,
         lines: Vec1::try_from_vec(lines).unwrap() // save, pest parsing requires at least one line per node
-    }))
+    })))
+}
+
+/// Checks the title with the same rule jumps use to refer to nodes
+fn parse_header_title(content: Pair<Rule>) -> Result<String, YarnSpinnerDialogLoaderError> {
+    let title = content.as_str().trim();
+    match YarnSpinnerParser::parse(Rule::title, title) {
+        Ok(parsed) if parsed.as_str() == title => Ok(title.to_string()),
+        _ => Err(InvalidTitle {
+            title: title.to_string(),
+            line: content.as_span().start_pos().line_col().0,
+        }),
+    }
+}
+
+fn parse_header(content: Pair<Rule>) -> (String, String) {
+    let mut fields = content.into_inner();
+    let key = fields.next().unwrap().as_str().to_string(); // safe, header always has a key and a value
+    let value = fields.next().unwrap().as_str().trim().to_string();
+    (key, value)
+}
+
+fn parse_when_header(content: Pair<Rule>) -> NodeCondition {
+    let condition = content.into_inner().next().unwrap(); // safe, when_header always has a condition
+    match condition.as_rule() {
+        Rule::when_always => NodeCondition::Always,
+        Rule::expression => NodeCondition::Expression(parse_expression(condition.into_inner())),
+        _ => unreachable!(),
+    }
 }
 
 fn parse_section_content(field: Pair<Rule>, lines: &mut Vec<LineType>) {
-    for content in field.into_inner() {
-        lines.push(parse_content(content));
+    let statements: Vec<Pair<Rule>> = field.into_inner().collect();
+    lines.extend(parse_statements(&statements));
+}
+
+/// Consecutive `->` or `=>` lines with the same indentation form a single option line or line group,
+/// the lines indented deeper than an option or line group item become its body
+fn parse_statements(statements: &[Pair<Rule>]) -> Vec<LineType> {
+    let mut lines = vec![];
+    let mut index = 0;
+
+    while index < statements.len() {
+        match statements[index].as_rule() {
+            Rule::option_line => lines.push(parse_option_group(statements, &mut index)),
+            Rule::line_group_item => lines.push(parse_line_group(statements, &mut index)),
+            _ => {
+                lines.push(parse_content(statements[index].clone()));
+                index += 1;
+            }
+        }
+    }
+
+    lines
+}
+
+fn parse_option_group(statements: &[Pair<Rule>], index: &mut usize) -> LineType {
+    let group_indentation = indentation(&statements[*index]);
+    let jump_per_option = jump_follows_every_option(statements, *index, group_indentation);
+    let mut possibilities = vec![];
+
+    while *index < statements.len()
+        && statements[*index].as_rule() == Rule::option_line
+        && indentation(&statements[*index]) == group_indentation
+    {
+        let mut possibility = parse_option_line(statements[*index].clone());
+        let body_start = *index + 1;
+        *index = body_end(statements, body_start, group_indentation);
+        possibility.body = parse_statements(&statements[body_start..*index]);
+
+        if jump_per_option {
+            possibility.body.push(parse_content(statements[*index].clone()));
+            *index += 1;
+        }
+
+        possibilities.push(possibility);
+    }
+
+    LineType::OptionLine {
+        speaker: None,
+        possibilities: Vec1::try_from_vec(possibilities).unwrap(), // safe as the group starts with an option line
     }
 }
 
+fn parse_line_group(statements: &[Pair<Rule>], index: &mut usize) -> LineType {
+    let group_indentation = indentation(&statements[*index]);
+    let mut items = vec![];
+
+    while *index < statements.len()
+        && statements[*index].as_rule() == Rule::line_group_item
+        && indentation(&statements[*index]) == group_indentation
+    {
+        let (line, condition, once) = parse_line_group_item(statements[*index].clone());
+        let body_start = *index + 1;
+        *index = body_end(statements, body_start, group_indentation);
+
+        let mut lines = vec![line];
+        lines.extend(parse_statements(&statements[body_start..*index]));
+        items.push(LineGroupItem { condition, once, lines });
+    }
+
+    LineType::LineGroup {
+        items: Vec1::try_from_vec(items).unwrap(), // safe as the group starts with a line group item
+    }
+}
+
+/// Whether every option of the group is directly followed by a `<<jump>>` at its own indentation,
+/// the older format which gives each option its jump instead of a body:
+/// ```text
+/// -> Yes
+/// <<jump Agree>>
+/// -> No
+/// <<jump Refuse>>
+/// ```
+/// Otherwise a line after the group runs whichever option was chosen.
+fn jump_follows_every_option(statements: &[Pair<Rule>], start: usize, group_indentation: usize) -> bool {
+    let at_group_level = |index: usize, rule: Rule| {
+        statements.get(index).is_some_and(|statement| {
+            statement.as_rule() == rule && indentation(statement) == group_indentation
+        })
+    };
+    let mut index = start;
+    while at_group_level(index, Rule::option_line) {
+        if !at_group_level(index + 1, Rule::jump_line) {
+            return false;
+        }
+        index += 2;
+    }
+    true
+}
+
+/// Index of the first statement after `start` which is not indented deeper than `indentation`
+fn body_end(statements: &[Pair<Rule>], start: usize, indentation_level: usize) -> usize {
+    statements[start..]
+        .iter()
+        .position(|statement| indentation(statement) <= indentation_level)
+        .map_or(statements.len(), |offset| start + offset)
+}
+
+fn indentation(statement: &Pair<Rule>) -> usize {
+    statement.as_span().start_pos().line_col().1
+}
+
 fn parse_content(content: Pair<Rule>) -> LineType {
     match content.as_rule() {
         Rule::set_line => parse_set_line(content),
         Rule::command_line => parse_command_line(content),
         Rule::dialog_line => parse_dialog_line(content),
-        Rule::option_lines => parse_option_lines(content),
         Rule::jump_line => parse_jump_line(content),
+        Rule::detour_line => parse_detour_line(content),
+        Rule::return_line => LineType::ReturnLine,
+        Rule::if_block => parse_if_block(content),
+        Rule::once_block => parse_once_block(content),
+        Rule::stop_line => LineType::StopLine,
+        Rule::declare_line => parse_declare_line(content),
+        Rule::enum_block => parse_enum_block(content),
         _ => unreachable!(),
     }
 }
 
+fn parse_if_block(content: Pair<Rule>) -> LineType {
+    let mut branches: Vec<IfBranch> = vec![];
+
+    for clause in content.into_inner() {
+        let mut condition: Option<Expression> = None;
+        let mut lines = vec![];
+
+        for clause_field in clause.into_inner() {
+            match clause_field.as_rule() {
+                Rule::expression => condition = Some(parse_expression(clause_field.into_inner())),
+                Rule::block_content => parse_section_content(clause_field, &mut lines),
+                _ => unreachable!(),
+            }
+        }
+
+        branches.push(IfBranch { condition, lines });
+    }
+
+    LineType::IfBlock {
+        branches: Vec1::try_from_vec(branches).unwrap(), // safe as pest requires the <<if>> clause
+    }
+}
+
+fn parse_once_block(content: Pair<Rule>) -> LineType {
+    let mut branches: Vec<IfBranch> = vec![];
+
+    for clause in content.into_inner() {
+        let mut condition: Option<Expression> = None;
+        let mut lines = vec![];
+
+        for clause_field in clause.into_inner() {
+            match clause_field.as_rule() {
+                Rule::once_statement => condition = parse_once_statement(clause_field),
+                Rule::block_content => parse_section_content(clause_field, &mut lines),
+                _ => unreachable!(),
+            }
+        }
+
+        branches.push(IfBranch { condition, lines });
+    }
+
+    LineType::OnceBlock {
+        branches: Vec1::try_from_vec(branches).unwrap(), // safe as pest requires the <<once>> clause
+    }
+}
+
 fn parse_set_line(content: Pair<Rule>) -> LineType {
     let mut variable_name = String::new();
-    let mut value = false;
+    let mut operator = String::new();
+    let mut value = Expression::Value(YarnValue::Bool(false));
+    let mut condition: Option<Expression> = None;
 
     for set_line_field in content.into_inner() {
         match set_line_field.as_rule() {
             Rule::variable_name => variable_name = set_line_field.as_str().to_string(),
-            Rule::boolean_value => value = set_line_field.as_str().parse::<bool>().unwrap(), // safe
+            Rule::set_operator => operator = set_line_field.as_str().to_string(),
+            Rule::expression => value = parse_expression(set_line_field.into_inner()),
+            Rule::if_statement => condition = Some(parse_if_statement(set_line_field)),
             _ => unreachable!(),
         }
     }
 
+    // compound assignments such as `+=` are stored as `$var = $var + value`
+    if let Some(operator) = operator.strip_suffix('=').and_then(|sign| BinaryOperator::from_str(sign).ok()) {
+        value = Expression::Binary {
+            operator,
+            left: Box::new(Expression::Variable(variable_name.clone())),
+            right: Box::new(value),
+        };
+    }
+
     LineType::SetLine {
         variable_name,
         value,
+        condition,
+    }
+}
+
+fn parse_declare_line(content: Pair<Rule>) -> LineType {
+    let mut variable_name = String::new();
+    let mut default_value = YarnValue::Bool(false);
+    let mut value_type: Option<YarnValueType> = None;
+
+    for declare_line_field in content.into_inner() {
+        match declare_line_field.as_rule() {
+            Rule::variable_name => variable_name = declare_line_field.as_str().to_string(),
+            Rule::value => default_value = parse_value(declare_line_field),
+            Rule::value_type => {
+                // any other name has to be one of the dialog's enums, checked once they are all known
+                let type_name = declare_line_field.as_str();
+                value_type = Some(YarnValueType::from_str(type_name).unwrap_or_else(|_| YarnValueType::Enum(type_name.to_string())));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    LineType::DeclareLine {
+        declaration: Declaration {
+            variable_name,
+            value_type: value_type.unwrap_or(default_value.value_type()),
+            default_value,
+        },
+    }
+}
+
+fn parse_enum_block(content: Pair<Rule>) -> LineType {
+    let mut name = String::new();
+    let mut cases = vec![];
+
+    for enum_field in content.into_inner() {
+        match enum_field.as_rule() {
+            Rule::enum_name => name = enum_field.as_str().to_string(),
+            Rule::enum_case => cases.push(enum_field.into_inner().as_str().to_string()),
+            _ => unreachable!(),
+        }
+    }
+
+    LineType::EnumBlock {
+        definition: EnumDefinition { name, cases },
     }
 }
 
 fn parse_command_line(content: Pair<Rule>) -> LineType {
+    let (func_name, args, condition) = parse_command(content);
+    LineType::CommandLine { func_name, args, condition }
+}
+
+/// Name, arguments and trailing condition of a command, inline commands never have a condition
+fn parse_command(content: Pair<Rule>) -> (String, Vec<CommandArgument>, Option<Expression>) {
     let mut func_name = String::new();
-    let mut args: Vec<String> = vec![];
+    let mut args: Vec<CommandArgument> = vec![];
+    let mut condition: Option<Expression> = None;
 
     for command_line_field in content.into_inner() {
         match command_line_field.as_rule() {
@@ -101,37 +628,128 @@ fn parse_command_line(content: Pair<Rule>) -> LineType {
             Rule::args => {
                 for command_arg_field in command_line_field.into_inner() {
                     match command_arg_field.as_rule() {
-                        Rule::arg => args.push(command_arg_field.as_str().to_string()),
+                        Rule::arg_text => args.push(CommandArgument::Text(command_arg_field.as_str().to_string())),
+                        Rule::string_value => args.push(CommandArgument::Text(unescape(
+                            command_arg_field.into_inner().next().unwrap().as_str(), // safe, string_value always contains string_inner
+                        ))),
+                        Rule::arg_expression => {
+                            let expression = command_arg_field.into_inner().next().unwrap(); // safe, arg_expression always wraps an expression
+                            args.push(CommandArgument::Expression(parse_expression(expression.into_inner())));
+                        }
                         _ => unreachable!(),
                     }
                 }
             }
+            Rule::if_statement => condition = Some(parse_if_statement(command_line_field)),
             _ => unreachable!(),
         }
     }
 
-    LineType::CommandLine { func_name, args }
+    (func_name, args, condition)
 }
 
 fn parse_dialog_line(content: Pair<Rule>) -> LineType {
-    let mut speaker = String::new();
+    let mut speaker: Option<String> = None;
     let mut text = String::new();
+    let mut expressions: Vec<Expression> = vec![];
+    let mut commands: Vec<InlineCommand> = vec![];
     let mut tags: Vec<Tag> = vec![];
+    let mut condition: Option<Expression> = None;
 
     for dialog_line_field in content.into_inner() {
         match dialog_line_field.as_rule() {
-            Rule::speaker => speaker = dialog_line_field.as_str().to_string(),
-            Rule::dialog => text = dialog_line_field.as_str().to_string(),
+            Rule::speaker => speaker = Some(dialog_line_field.as_str().to_string()),
+            Rule::dialog => (text, expressions, commands) = parse_dialog(dialog_line_field),
             Rule::tags => tags.push(parse_tag(dialog_line_field)),
+            Rule::if_statement => condition = Some(parse_if_statement(dialog_line_field)),
             _ => unreachable!(),
         }
     }
 
+    let line_id = take_line_id(&mut tags);
     LineType::DialogLine {
         speaker,
         text,
+        expressions,
+        commands,
         tags,
+        line_id,
+        condition,
+    }
+}
+
+/// Replaces every `{expression}` in the text with a `{n}` placeholder pointing at the parsed expression
+/// and every inline command with a self-closing markup tag pointing at the parsed command. Escaped
+/// characters are kept for the markup parser, line continuations are removed.
+fn parse_dialog(content: Pair<Rule>) -> (String, Vec<Expression>, Vec<InlineCommand>) {
+    let start = content.as_span().start();
+    let source = content.as_str();
+    let mut text = String::new();
+    let mut expressions = vec![];
+    let mut commands = vec![];
+    let mut copied = 0;
+
+    for field in content.into_inner() {
+        let span = field.as_span();
+        text.push_str(&source[copied..span.start() - start]);
+        match field.as_rule() {
+            Rule::interpolation => {
+                text.push_str(&format!("{{{}}}", expressions.len()));
+                let expression = field.into_inner().next().unwrap(); // safe, interpolation always wraps an expression
+                expressions.push(parse_expression(expression.into_inner()));
+            }
+            Rule::inline_command => {
+                text.push_str(&format!("[{} {}={}/]", INLINE_COMMAND_ATTRIBUTE, INLINE_COMMAND_INDEX_PROPERTY, commands.len()));
+                let (func_name, args, _) = parse_command(field);
+                commands.push(InlineCommand { func_name, args });
+            }
+            Rule::escaped_char => text.push_str(field.as_str()),
+            Rule::line_continuation => {}
+            _ => unreachable!(),
+        }
+        copied = span.end() - start;
+    }
+    // the whitespace separating the text from a trailing condition or tags is not part of it
+    text.push_str(source[copied..].trim_end());
+
+    (text, expressions, commands)
+}
+
+fn parse_line_group_item(content: Pair<Rule>) -> (LineType, Option<Expression>, bool) {
+    let mut speaker: Option<String> = None;
+    let mut text = String::new();
+    let mut expressions: Vec<Expression> = vec![];
+    let mut condition: Option<Expression> = None;
+    let mut once = false;
+    let mut commands: Vec<InlineCommand> = vec![];
+    let mut tags: Vec<Tag> = vec![];
+
+    for item_field in content.into_inner() {
+        match item_field.as_rule() {
+            Rule::speaker => speaker = Some(item_field.as_str().to_string()),
+            Rule::dialog => (text, expressions, commands) = parse_dialog(item_field),
+            Rule::if_statement => condition = Some(parse_if_statement(item_field)),
+            Rule::once_statement => {
+                once = true;
+                condition = parse_once_statement(item_field);
+            }
+            Rule::tags => tags.push(parse_tag(item_field)),
+            _ => unreachable!(),
+        }
     }
+
+    let line_id = take_line_id(&mut tags);
+    let line = LineType::DialogLine {
+        speaker,
+        text,
+        expressions,
+        commands,
+        tags,
+        line_id,
+        // the item's condition decides whether it is a candidate of the group
+        condition: None,
+    };
+    (line, condition, once)
 }
 
 fn parse_tag(content: Pair<Rule>) -> Tag {
@@ -149,91 +767,166 @@ fn parse_tag(content: Pair<Rule>) -> Tag {
     Tag { name, value }
 }
 
-fn parse_option_lines(content: Pair<Rule>) -> LineType {
-    let mut option_possibilities: Vec<OptionPossibility> = vec![];
-    let speaker = String::from("Player");
-
-    for option_lines_field in content.into_inner() {
-        match option_lines_field.as_rule() {
-            Rule::option_line => {
-                let mut text = String::new();
-                let mut node_title = String::new();
-                let mut condition: Option<Condition> = None;
-
-                for option_line_field in option_lines_field.into_inner() {
-                    match option_line_field.as_rule() {
-                        Rule::option_dialog_line => {
-                            for dialog_line_field in option_line_field.into_inner() {
-                                match dialog_line_field.as_rule() {
-                                    Rule::speaker => {}
-                                    Rule::dialog => text = dialog_line_field.as_str().to_string(),
-                                    Rule::if_statement => {
-                                        condition = Some(parse_if_statement(dialog_line_field))
-                                    }
-                                    _ => unreachable!(),
-                                }
-                            }
+/// Removes the `#line:` tag from the tags, lines without one get their id once the whole file is parsed
+fn take_line_id(tags: &mut Vec<Tag>) -> String {
+    match tags.iter().position(|tag| tag.name == LINE_ID_TAG) {
+        Some(index) => format!("{}:{}", LINE_ID_TAG, tags.remove(index).value),
+        None => String::new(),
+    }
+}
+
+fn parse_option_line(content: Pair<Rule>) -> OptionPossibility {
+    let mut speaker: Option<String> = None;
+    let mut text = String::new();
+    let mut expressions: Vec<Expression> = vec![];
+    let mut condition: Option<Expression> = None;
+    let mut once = false;
+    let mut tags: Vec<Tag> = vec![];
+
+    for option_line_field in content.into_inner() {
+        match option_line_field.as_rule() {
+            Rule::option_dialog_line => {
+                for dialog_line_field in option_line_field.into_inner() {
+                    match dialog_line_field.as_rule() {
+                        Rule::speaker => speaker = Some(dialog_line_field.as_str().to_string()),
+                        // options do not run inline commands, their markers are dropped with the markup
+                        Rule::dialog => (text, expressions, _) = parse_dialog(dialog_line_field),
+                        Rule::if_statement => {
+                            condition = Some(parse_if_statement(dialog_line_field))
                         }
-                        Rule::jump_line => {
-                            for jump_line_field in option_line_field.into_inner() {
-                                match jump_line_field.as_rule() {
-                                    Rule::title => {
-                                        node_title = jump_line_field.as_str().to_string()
-                                    }
-                                    _ => unreachable!(),
-                                }
-                            }
+                        Rule::once_statement => {
+                            once = true;
+                            condition = parse_once_statement(dialog_line_field);
                         }
+                        Rule::tags => tags.push(parse_tag(dialog_line_field)),
                         _ => unreachable!(),
                     }
                 }
-
-                option_possibilities.push(OptionPossibility {
-                    text,
-                    jump_to_node_title: node_title,
-                    jump_to_node: Weak::<RwLock<YarnSpinnerNode>>::new(),
-                    condition,
-                    used: false,
-                });
             }
             _ => unreachable!(),
         }
     }
 
-    LineType::OptionLine {
+    OptionPossibility {
         speaker,
-        possibilities: Vec1::try_from_vec(option_possibilities).unwrap(), // safe as pest requires at least one possibility
+        text,
+        line_id: take_line_id(&mut tags),
+        expressions,
+        tags,
+        condition,
+        once,
+        body: vec![],
+        used: false,
     }
 }
 
-fn parse_if_statement(dialog_line_field: Pair<Rule>) -> Condition {
-    let mut variable_name = String::new();
-    let mut condition_sign = String::new();
-    let mut value = false;
-
-    for if_statement_field in dialog_line_field.into_inner() {
-        match if_statement_field.as_rule() {
-            Rule::variable_name => variable_name = if_statement_field.as_str().to_string(),
-            Rule::condition => condition_sign = if_statement_field.as_str().to_string(),
-            Rule::boolean_value => value = if_statement_field.as_str().to_string().parse::<bool>().unwrap(), // safe as boolean_value contains either 'true' or 'false'
+fn parse_if_statement(dialog_line_field: Pair<Rule>) -> Expression {
+    let expression = dialog_line_field.into_inner().next().unwrap(); // safe, if_statement always wraps an expression
+    parse_expression(expression.into_inner())
+}
+
+/// Condition of `<<once if ...>>`, a plain `<<once>>` has none
+fn parse_once_statement(content: Pair<Rule>) -> Option<Expression> {
+    content.into_inner().next().map(|expression| parse_expression(expression.into_inner()))
+}
+
+fn parse_expression(pairs: Pairs<Rule>) -> Expression {
+    PRATT_PARSER
+        .map_primary(|primary| match primary.as_rule() {
+            Rule::value => Expression::Value(parse_value(primary)),
+            Rule::variable => Expression::Variable(primary.into_inner().as_str().to_string()),
+            Rule::expression => parse_expression(primary.into_inner()),
+            Rule::function_call => parse_function_call(primary),
+            _ => unreachable!(),
+        })
+        .map_prefix(|operator, operand| {
+            let operator = match operator.as_rule() {
+                Rule::not_operator => UnaryOperator::Not,
+                Rule::negate_operator => UnaryOperator::Negate,
+                _ => unreachable!(),
+            };
+            Expression::Unary { operator, operand: Box::new(operand) }
+        })
+        .map_infix(|left, operator, right| Expression::Binary {
+            operator: BinaryOperator::from_str(operator.as_str()).unwrap(), // safe, grammar only accepts known operators
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+        .parse(pairs)
+}
+
+fn parse_function_call(content: Pair<Rule>) -> Expression {
+    let mut name = String::new();
+    let mut arguments: Vec<Expression> = vec![];
+
+    for function_call_field in content.into_inner() {
+        match function_call_field.as_rule() {
+            Rule::function_name => name = function_call_field.as_str().to_string(),
+            Rule::expression => arguments.push(parse_expression(function_call_field.into_inner())),
             _ => unreachable!(),
         }
     }
-    Condition {
-        variable_name,
-        condition: ConditionType::from_str(condition_sign.as_str()).expect("Wrong condition sign"),
-        value,
+
+    Expression::FunctionCall { name, arguments }
+}
+
+fn parse_value(content: Pair<Rule>) -> YarnValue {
+    let literal = content.into_inner().next().unwrap(); // safe, value always wraps a single literal
+    match literal.as_rule() {
+        Rule::boolean_value => YarnValue::Bool(literal.as_str().parse::<bool>().unwrap()), // safe as boolean_value contains either 'true' or 'false'
+        Rule::number_value => YarnValue::Number(literal.as_str().parse::<f32>().unwrap()), // safe, grammar only accepts digits
+        Rule::string_value => YarnValue::String(unescape(literal.into_inner().next().unwrap().as_str())),
+        Rule::enum_value => {
+            let mut fields = literal.into_inner();
+            YarnValue::Enum {
+                enum_name: fields.next().unwrap().as_str().to_string(), // safe, enum_value is name.case
+                case: fields.next().unwrap().as_str().to_string(),
+            }
+        }
+        _ => unreachable!(),
     }
 }
 
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some(escaped) => result.push(escaped),
+                None => {}
+            },
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
 fn parse_jump_line(content: Pair<Rule>) -> LineType {
-    let node_title = content
-        .into_inner()
-        .find_map(|field| match field.as_rule() {
-            Rule::title => Some(field.as_str().to_string()),
-            _ => None,
-        })
-        .expect("Jump line missing title");
+    let (target, condition) = parse_jump_target(content);
+    LineType::JumpLine { node: Weak::<RwLock<YarnSpinnerNode>>::new(), target, condition }
+}
+
+fn parse_detour_line(content: Pair<Rule>) -> LineType {
+    let (target, condition) = parse_jump_target(content);
+    LineType::DetourLine { node: Weak::<RwLock<YarnSpinnerNode>>::new(), target, condition }
+}
+
+/// Target and trailing condition of a jump or detour line
+fn parse_jump_target(content: Pair<Rule>) -> (JumpTarget, Option<Expression>) {
+    let mut target: Option<JumpTarget> = None;
+    let mut condition: Option<Expression> = None;
+
+    for field in content.into_inner() {
+        match field.as_rule() {
+            Rule::title => target = Some(JumpTarget::Title(field.as_str().to_string())),
+            Rule::expression => target = Some(JumpTarget::Expression(parse_expression(field.into_inner()))),
+            Rule::if_statement => condition = Some(parse_if_statement(field)),
+            _ => unreachable!(),
+        }
+    }
 
-    LineType::JumpLine { node: Weak::<RwLock<YarnSpinnerNode>>::new(), node_title: node_title }
+    (target.expect("Line missing jump target"), condition)
 }
@@ -0,0 +1,97 @@
+mod common;
+
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use bevy_yarnspinner::bevy_detective_derive::yarn_command;
+use bevy_yarnspinner::dialog_runner::runner::COMMAND_REGISTRY;
+use common::TestDialog;
+
+/// Calls of the commands below, every test uses its own command names
+static CALLS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+fn record(call: String) {
+    CALLS.lock().unwrap().push(call);
+}
+
+fn calls_of(command: &str) -> Vec<String> {
+    let prefix = format!("{}(", command);
+    CALLS.lock().unwrap().iter().filter(|call| call.starts_with(&prefix)).cloned().collect()
+}
+
+#[test]
+fn quoted_strings_floats_and_negatives() {
+    #[yarn_command]
+    fn show_text(_commands: &mut Commands, text: String, seconds: f32, offset: i32, loud: bool) {
+        record(format!("show_text({:?}, {}, {}, {})", text, seconds, offset, loud));
+    }
+
+    let source = r#"title: Start
+---
+<<show_text "Hello \"there\"" 3.5 -2 true>>
+<<show_text "" -0.25 0 FALSE>>
+===
+"#;
+    TestDialog::new(source).play_choosing(&[]);
+    assert_eq!(
+        calls_of("show_text"),
+        [r#"show_text("Hello \"there\"", 3.5, -2, true)"#, r#"show_text("", -0.25, 0, false)"#]
+    );
+}
+
+#[test]
+fn expression_arguments_are_evaluated_against_the_context() {
+    #[yarn_command("move")]
+    fn move_to(_commands: &mut Commands, who: String, x: f32, steps: i32, run: bool) {
+        record(format!("move({}, {}, {}, {})", who, x, steps, run));
+    }
+
+    let source = r#"title: Start
+---
+<<declare $x = 2>>
+<<declare $hurry = true>>
+<<move Alice {$x + 0.5} {$x * -3} {$hurry}>>
+<<set $hurry to false>>
+<<move {"Bo" + "b"} {$x} 1 {not $hurry}>>
+===
+"#;
+    TestDialog::new(source).play_choosing(&[]);
+    assert_eq!(calls_of("move"), ["move(Alice, 2.5, -6, true)", "move(Bob, 2, 1, true)"]);
+}
+
+#[test]
+fn raw_tokens_are_still_split_at_whitespace() {
+    #[yarn_command]
+    fn wave(_commands: &mut Commands, first: String, second: String) {
+        record(format!("wave({}, {})", first, second));
+    }
+
+    TestDialog::new("title: Start\n---\n<<wave hello-there world.1>>\n===\n").play_choosing(&[]);
+    assert_eq!(calls_of("wave"), ["wave(hello-there, world.1)"]);
+}
+
+#[test]
+fn malformed_arguments_are_reported() {
+    #[yarn_command]
+    fn count_to(_commands: &mut Commands, number: i32) {
+        record(format!("count_to({})", number));
+    }
+
+    let error = |source: &str| TestDialog::new(source).try_next_event().unwrap_err().to_string();
+    assert_eq!(
+        error("title: Start\n---\n<<count_to many>>\n===\n"),
+        "Invalid arguments for command count_to: \"many\" is not an integer"
+    );
+    assert_eq!(
+        error("title: Start\n---\n<<count_to>>\n===\n"),
+        "Invalid arguments for command count_to: argument 0 is missing"
+    );
+    assert_eq!(
+        error("title: Start\n---\n<<count_to 1.5>>\n===\n"),
+        "Invalid arguments for command count_to: \"1.5\" is not an integer"
+    );
+
+    // a failed command leaves the registry usable
+    TestDialog::new("title: Start\n---\n<<count_to 3>>\n===\n").play_choosing(&[]);
+    assert_eq!(calls_of("count_to"), ["count_to(3)"]);
+}
@@ -0,0 +1,93 @@
+#![allow(dead_code)]
+
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_yarnspinner::dialog_runner::components::{DialogCommand, DialogEvent};
+use bevy_yarnspinner::dialog_runner::dialog_runner_error::DialogRunnerError;
+use bevy_yarnspinner::dialog_runner::runner::DialogRunner;
+use bevy_yarnspinner::parsing::value::YarnValue;
+use bevy_yarnspinner::parsing::yarn_spinner_parsing::load_from_file;
+
+pub type Context = HashMap<String, YarnValue>;
+
+/// A runner together with the state and the world it needs to produce events
+pub struct TestDialog {
+    pub runner: DialogRunner<Context>,
+    pub context: Context,
+    world: World,
+    queue: CommandQueue,
+}
+
+impl TestDialog {
+    /// Loads `source` and starts at the `Start` node
+    pub fn new(source: &str) -> Self {
+        Self::with_context(source, Context::new())
+    }
+
+    pub fn with_context(source: &str, mut context: Context) -> Self {
+        let dialog = load_from_file(source, &Default::default()).unwrap();
+        let runner = DialogRunner::create_from_dialog(&dialog, "Start", &mut context).unwrap();
+        Self {
+            runner,
+            context,
+            world: World::new(),
+            queue: CommandQueue::default(),
+        }
+    }
+
+    pub fn next_event(&mut self) -> DialogEvent {
+        self.try_next_event().unwrap()
+    }
+
+    pub fn try_next_event(&mut self) -> Result<DialogEvent, DialogRunnerError> {
+        let mut commands = Commands::new(&mut self.queue, &self.world);
+        self.runner.next_event(&mut self.context, &mut commands)
+    }
+
+    /// Runs an inline command of a line the way a dialog UI would
+    pub fn execute(&mut self, command: &DialogCommand) -> Result<(), DialogRunnerError> {
+        let mut commands = Commands::new(&mut self.queue, &self.world);
+        command.execute(&mut commands)
+    }
+
+    pub fn choose(&mut self, option_id: usize) {
+        self.runner.make_decision(option_id).unwrap();
+    }
+
+    /// Plays until the dialog ends, picking the option at `choices[n]` for the n-th options event
+    /// and the first option once `choices` runs out. Returns every line and option text in the
+    /// order they were presented, options prefixed with `-> `.
+    pub fn play_choosing(&mut self, choices: &[usize]) -> Vec<String> {
+        let mut choices = choices.iter();
+        let mut presented = vec![];
+        loop {
+            match self.next_event() {
+                DialogEvent::Dialog { text, .. } => presented.push(text),
+                DialogEvent::Options { options, .. } => {
+                    presented.extend(options.iter().map(|option| format!("-> {}", option.text)));
+                    let choice = choices.next().copied().unwrap_or(0);
+                    self.choose(options[choice].id);
+                }
+                DialogEvent::Waiting => unreachable!(),
+                DialogEvent::End => return presented,
+            }
+        }
+    }
+}
+
+/// Plays the dialog from `Start`, always picking the first option, and returns every line and
+/// option text in the order they were presented
+pub fn play(source: &str) -> Vec<String> {
+    TestDialog::new(source).play_choosing(&[])
+}
+
+/// First event of the dialog started at `Start`
+pub fn first_event(source: &str) -> DialogEvent {
+    TestDialog::new(source).next_event()
+}
+
+pub fn node_titles(source: &str) -> Vec<String> {
+    let dialog = load_from_file(source, &Default::default()).unwrap();
+    dialog.nodes.iter().map(|node| node.read().unwrap().title.clone()).collect()
+}
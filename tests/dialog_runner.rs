@@ -0,0 +1,393 @@
+mod common;
+
+use bevy_yarnspinner::dialog_runner::components::{DialogEvent, DialogOption};
+use bevy_yarnspinner::dialog_runner::dialog_runner_error::DialogRunnerError;
+use bevy_yarnspinner::parsing::value::YarnValue;
+use common::{first_event, play, Context, TestDialog};
+
+#[test]
+fn option_tags_and_conditions_in_any_order() {
+    let DialogEvent::Options { options, .. } = first_event(include_str!("corpus/option_tags.yarn")) else {
+        panic!("expected options");
+    };
+    let tags = |option: &DialogOption| -> Vec<String> {
+        option.tags.iter().map(|tag| format!("{}:{}", tag.name, tag.value)).collect()
+    };
+    let texts: Vec<&str> = options.iter().map(|option| option.text.as_str()).collect();
+    assert_eq!(texts, ["Buy the sword", "Haggle", "Leave"]);
+    assert_eq!(tags(&options[0]), ["price:100"]);
+    assert_eq!(tags(&options[1]), ["mood:cheeky"]);
+    assert_eq!(options[1].line_id, "line:haggle");
+    assert_eq!(tags(&options[2]), ["exit:true"]);
+}
+
+#[test]
+fn line_conditions() {
+    let source = include_str!("corpus/line_conditions.yarn");
+    assert_eq!(
+        play(source),
+        [
+            "The gate is locked.",
+            "Nice weather today.",
+            "Did you hear the news?",
+            "Here are 10 coins.",
+            "Off you go.",
+        ]
+    );
+}
+
+#[test]
+fn conditions_starting_with_literals_operators_and_calls() {
+    assert_eq!(
+        play(include_str!("corpus/conditions.yarn")),
+        ["-> D", "one"]
+    );
+}
+
+#[test]
+fn unknown_commands_are_reported() {
+    let mut dialog = TestDialog::new("title: Start\n---\nMae: Hi <<no_such_inline>> there\n<<no_such_command>>\n===\n");
+    let DialogEvent::Dialog { commands, .. } = dialog.next_event() else {
+        panic!("expected a line");
+    };
+    let error = dialog.execute(&commands[0]).unwrap_err();
+    assert!(matches!(error, DialogRunnerError::UnknownCommand { name } if name == "no_such_inline"));
+
+    let error = dialog.try_next_event().unwrap_err();
+    assert!(matches!(error, DialogRunnerError::UnknownCommand { name } if name == "no_such_command"));
+}
+
+#[test]
+fn commands_starting_with_jump_or_detour_are_commands() {
+    for name in ["jumpscare", "jump_cut", "detourAll"] {
+        let source = format!("title: Start\n---\n<<{}>>\n===\ntitle: scare\n---\nBoo\n===\n", name);
+        let error = TestDialog::new(&source).try_next_event().unwrap_err();
+        assert!(matches!(error, DialogRunnerError::UnknownCommand { name: found } if found == name));
+    }
+    assert_eq!(play("title: Start\n---\n<<jump{\"Other\"}>>\n===\ntitle: Other\n---\nOther\n===\n"), ["Other"]);
+}
+
+#[test]
+fn line_after_an_option_group_runs_for_every_option() {
+    let source = "title: Start\n---\n-> A\n-> B\n<<jump End>>\n===\ntitle: End\n---\nThe end\n===\n";
+    assert_eq!(TestDialog::new(source).play_choosing(&[0]), ["-> A", "-> B", "The end"]);
+    assert_eq!(TestDialog::new(source).play_choosing(&[1]), ["-> A", "-> B", "The end"]);
+}
+
+#[test]
+fn jump_below_each_option_belongs_to_that_option() {
+    let source = "title: Start\n---\n-> A\n<<jump First>>\n-> B\n<<jump Second>>\n===\n\
+        title: First\n---\nFirst\n===\ntitle: Second\n---\nSecond\n===\n";
+    assert_eq!(TestDialog::new(source).play_choosing(&[0]), ["-> A", "-> B", "First"]);
+    assert_eq!(TestDialog::new(source).play_choosing(&[1]), ["-> A", "-> B", "Second"]);
+}
+
+#[test]
+fn start_and_reset_pick_an_eligible_node_group_member() {
+    let source = "title: Start\nwhen: false\n---\nNever\n===\ntitle: Start\nwhen: always\n---\nAlways\n===\n\
+        title: Other\n---\nOther\n===\n";
+    let mut dialog = TestDialog::new(source);
+    assert_eq!(dialog.play_choosing(&[]), ["Always"]);
+
+    dialog.runner.reset_to("Start", &dialog.context).unwrap();
+    assert_eq!(dialog.play_choosing(&[]), ["Always"]);
+    dialog.runner.reset_to("Other", &dialog.context).unwrap();
+    assert_eq!(dialog.play_choosing(&[]), ["Other"]);
+    assert!(dialog.runner.reset_to("Missing", &dialog.context).is_err());
+}
+
+#[test]
+fn expressions() {
+    let source = r#"title: Start
+---
+<<set $a to 2 + 3 * 4>>
+<<set $b to (2 + 3) * 4>>
+<<set $c to 17 % 5>>
+<<set $d to $a > 10 and not ($b < 10)>>
+<<set $e to true xor true>>
+<<set $f to "con" + "cat">>
+<<set $g to -$c + 1>>
+<<set $a -= 4>>
+<<set $b /= 8>>
+{$a} {$b} {$c} {$d} {$e} {$f} {$g}
+<<if $a == 10 && $f is "concat" and $b >= 2.5 and $b <= 2.5 and $c neq 3 or false>>
+    Conditions hold
+<<endif>>
+===
+"#;
+    assert_eq!(play(source), ["10 2.5 2 True False concat -1", "Conditions hold"]);
+}
+
+#[test]
+fn undefined_variables_in_conditions_are_reported() {
+    for condition in ["$undeclared", "not $undeclared", "$undeclared == 1"] {
+        let source = format!("title: Start\n---\nShown <<if {}>>\n===\n", condition);
+        let error = TestDialog::new(&source).try_next_event().unwrap_err();
+        assert!(matches!(error, DialogRunnerError::UndefinedVariable { variable_name } if variable_name == "undeclared"));
+    }
+    let error = TestDialog::new("title: Start\n---\n-> Ask <<if $undeclared>>\n===\n").try_next_event().unwrap_err();
+    assert_eq!(error.to_string(), "Variable is not defined: $undeclared");
+}
+
+#[test]
+fn set_enforces_the_declared_type() {
+    let source = "title: Start\n---\n<<declare $gold = 0>>\n<<set $gold to 5>>\nGold {$gold}\n<<set $gold to \"lots\">>\n===\n";
+    let mut dialog = TestDialog::new(source);
+    let DialogEvent::Dialog { text, .. } = dialog.next_event() else {
+        panic!("expected a line");
+    };
+    assert_eq!(text, "Gold 5");
+    let error = dialog.try_next_event().unwrap_err();
+    assert_eq!(error.to_string(), "Variable $gold is declared as Number, but String was assigned");
+    assert_eq!(dialog.context["gold"], YarnValue::Number(5.0));
+
+    // undeclared variables take any value
+    assert_eq!(play("title: Start\n---\n<<set $free to 1>>\n<<set $free to \"one\">>\n{$free}\n===\n"), ["one"]);
+}
+
+#[test]
+fn nested_if_blocks() {
+    let source = r#"title: Start
+---
+<<declare $gold = 15>>
+<<if $gold > 100>>
+    Rich
+<<elseif $gold > 10>>
+    Comfortable
+    <<if $gold % 2 == 0>>
+        Even
+    <<else>>
+        Odd
+        -> Spend
+            <<set $gold to 0>>
+            <<if $gold == 0>>
+                Broke
+            <<endif>>
+        -> Save
+    <<endif>>
+    Still comfortable
+<<else>>
+    Poor
+<<endif>>
+After
+===
+"#;
+    assert_eq!(
+        TestDialog::new(source).play_choosing(&[0]),
+        ["Comfortable", "Odd", "-> Spend", "-> Save", "Broke", "Still comfortable", "After"]
+    );
+    assert_eq!(
+        TestDialog::new(source).play_choosing(&[1]),
+        ["Comfortable", "Odd", "-> Spend", "-> Save", "Still comfortable", "After"]
+    );
+
+    let mut context = Context::new();
+    context.insert("gold".to_string(), YarnValue::Number(0.0));
+    assert_eq!(TestDialog::with_context(source, context).play_choosing(&[]), ["Poor", "After"]);
+}
+
+#[test]
+fn builtin_functions() {
+    let source = r#"title: Start
+---
+{round(2.6)} {round_places(3.14159, 2)} {floor(2.6)} {ceil(2.1)} {int(-2.7)} {decimal(2.25)}
+{inc(1.5)} {dec(1.5)} {inc(4)} {dec(4)}
+{string(3) + "!"} {number("4") + 1} {bool("true")}
+<<set $roll to dice(6)>>
+<<set $range to random_range(3, 5)>>
+<<set $random to random()>>
+<<if $roll >= 1 and $roll <= 6 and $range >= 3 and $range <= 5 and $random >= 0 and $random < 1>>
+    In range
+<<endif>>
+===
+"#;
+    assert_eq!(
+        play(source),
+        ["3 3.14 2 3 -2 0.25", "2 1 5 3", "3! 5 True", "In range"]
+    );
+}
+
+#[test]
+fn visit_counts() {
+    let source = r#"title: Start
+---
+{visited("Shop")} {visited_count("Shop")}
+<<if visited_count("Shop") < 2>>
+    <<jump Shop>>
+<<endif>>
+Done {visited_count("Shop")} {visited_count("Start")}
+===
+title: Shop
+---
+Shopping
+<<jump Start>>
+===
+"#;
+    let mut dialog = TestDialog::new(source);
+    assert_eq!(
+        dialog.play_choosing(&[]),
+        ["False 0", "Shopping", "True 1", "Shopping", "True 2", "Done 2 3"]
+    );
+    assert_eq!(dialog.runner.visit_count("Shop"), 2);
+}
+
+#[test]
+fn custom_and_unknown_functions() {
+    let mut dialog = TestDialog::new("title: Start\n---\n{double(21)}\n{missing(1)}\n===\n");
+    dialog.runner.register_function("double", 1, Box::new(|args| Ok(YarnValue::Number(args[0].as_number()? * 2.0))));
+    let DialogEvent::Dialog { text, .. } = dialog.next_event() else {
+        panic!("expected a line");
+    };
+    assert_eq!(text, "42");
+    let error = dialog.try_next_event().unwrap_err();
+    assert!(matches!(error, DialogRunnerError::UnknownFunction { name } if name == "missing"));
+
+    let mut dialog = TestDialog::new("title: Start\n---\n{round(1, 2)}\n===\n");
+    let error = dialog.try_next_event().unwrap_err();
+    assert!(matches!(error, DialogRunnerError::FunctionArity { expected: 1, found: 2, .. }));
+}
+
+#[test]
+fn detours_return_after_the_detour_line() {
+    let source = r#"title: Start
+---
+Before
+<<detour Greeting>>
+Between
+<<detour Shop>>
+After
+===
+title: Greeting
+---
+Hello
+Bye
+===
+title: Shop
+---
+Welcome to the shop
+<<detour Greeting>>
+<<if true>>
+    <<return>>
+<<endif>>
+Never shown
+===
+"#;
+    let mut dialog = TestDialog::new(source);
+    let mut presented = vec![];
+    let mut call_stacks = vec![];
+    while let DialogEvent::Dialog { text, .. } = dialog.next_event() {
+        if text == "Hello" || text == "Welcome to the shop" {
+            call_stacks.push(dialog.runner.call_stack());
+        }
+        presented.push(text);
+    }
+    assert_eq!(
+        presented,
+        ["Before", "Hello", "Bye", "Between", "Welcome to the shop", "Hello", "Bye", "After"]
+    );
+    let expected: [&[&str]; 3] = [&["Start"], &["Start"], &["Start", "Shop"]];
+    assert_eq!(call_stacks, expected);
+}
+
+#[test]
+fn jump_and_detour_targets_from_expressions() {
+    let source = r#"title: Start
+---
+<<declare $next = "Shop">>
+<<detour {"Greet" + "ing"}>>
+<<jump {$next}>>
+===
+title: Greeting
+---
+Hello
+===
+title: Shop
+---
+Welcome to the shop
+===
+"#;
+    assert_eq!(play(source), ["Hello", "Welcome to the shop"]);
+
+    let source = "title: Start\n---\n<<declare $next = \"Nope\">>\nBefore\n<<jump {$next}>>\n===\n";
+    let mut dialog = TestDialog::new(source);
+    dialog.next_event();
+    let error = dialog.try_next_event().unwrap_err();
+    assert_eq!(error.to_string(), "No node to jump to is titled: Nope");
+    assert!(matches!(error, DialogRunnerError::UnknownJumpTarget { node_name } if node_name == "Nope"));
+}
+
+#[test]
+fn return_without_detour_and_stop_inside_a_detour_end_the_dialog() {
+    assert_eq!(play("title: Start\n---\nOne\n<<return>>\nTwo\n===\n"), ["One"]);
+    assert_eq!(
+        play("title: Start\n---\n<<detour Trap>>\nNever\n===\ntitle: Trap\n---\nCaught\n<<stop>>\n===\n"),
+        ["Caught"]
+    );
+}
+
+const ONCE_SOURCE: &str = r#"title: Start
+---
+<<once>>
+    First visit
+<<else>>
+    Welcome back
+<<endonce>>
+-> Only once <<once>>
+    Picked once
+-> Always
+=> Greeting <<once>>
+=> Same old
+===
+"#;
+
+#[test]
+fn once_content_is_only_presented_the_first_time() {
+    let mut dialog = TestDialog::new(ONCE_SOURCE);
+    assert_eq!(
+        dialog.play_choosing(&[]),
+        ["First visit", "-> Only once", "-> Always", "Picked once", "Greeting"]
+    );
+    dialog.runner.reset_to("Start", &dialog.context).unwrap();
+    assert_eq!(dialog.play_choosing(&[]), ["Welcome back", "-> Always", "Same old"]);
+}
+
+#[test]
+fn once_options_are_only_used_up_when_chosen() {
+    let mut dialog = TestDialog::new(ONCE_SOURCE);
+    assert_eq!(
+        dialog.play_choosing(&[1]),
+        ["First visit", "-> Only once", "-> Always", "Greeting"]
+    );
+    dialog.runner.reset_to("Start", &dialog.context).unwrap();
+    assert_eq!(
+        dialog.play_choosing(&[0]),
+        ["Welcome back", "-> Only once", "-> Always", "Picked once", "Same old"]
+    );
+}
+
+#[test]
+fn option_groups_without_available_options_are_skipped() {
+    let source = "title: Start\n---\n-> Only <<once>>\nAfter\n===\n";
+    let mut dialog = TestDialog::new(source);
+    assert_eq!(dialog.play_choosing(&[]), ["-> Only", "After"]);
+    dialog.runner.reset_to("Start", &dialog.context).unwrap();
+    assert_eq!(dialog.play_choosing(&[]), ["After"]);
+
+    let source = "title: Start\n---\n-> Locked <<if false>>\n-> Hidden <<if 1 > 2>>\nAfter\n===\n";
+    assert_eq!(play(source), ["After"]);
+}
+
+#[test]
+fn seen_once_content_survives_save_and_load() {
+    let mut dialog = TestDialog::new(ONCE_SOURCE);
+    dialog.play_choosing(&[]);
+    let state = dialog.runner.save_state();
+
+    let mut restored = TestDialog::new(ONCE_SOURCE);
+    restored.runner.load_state(state);
+    assert_eq!(restored.play_choosing(&[]), ["Welcome back", "-> Always", "Same old"]);
+    assert_eq!(
+        TestDialog::new(ONCE_SOURCE).play_choosing(&[]),
+        ["First visit", "-> Only once", "-> Always", "Picked once", "Greeting"]
+    );
+}
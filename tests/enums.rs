@@ -0,0 +1,119 @@
+mod common;
+
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use bevy_yarnspinner::dialog_runner::components::DialogEvent;
+use bevy_yarnspinner::dialog_runner::context::StateContext;
+use bevy_yarnspinner::dialog_runner::runner::DialogRunner;
+use bevy_yarnspinner::parsing::value::{YarnEnum, YarnValue};
+use bevy_yarnspinner::parsing::yarn_spinner_parsing::load_from_file;
+use common::{play, TestDialog};
+
+const MOOD: &str = "<<enum Mood>>\n    <<case Happy>>\n    <<case Grumpy>>\n<<endenum>>\n";
+
+#[test]
+fn enum_sets_comparisons_and_interpolation() {
+    let source = format!(
+        r#"title: Start
+---
+{}<<declare $mood = Mood.Happy>>
+Mood {{$mood}}
+<<if $mood == Mood.Happy and $mood != "Happy">>
+    Happy as declared
+<<endif>>
+<<set $mood to Mood.Grumpy>>
+<<if $mood is Mood.Grumpy>>
+    Now {{$mood}}
+<<endif>>
+===
+"#,
+        MOOD
+    );
+    assert_eq!(play(&source), ["Mood Happy", "Happy as declared", "Now Grumpy"]);
+}
+
+#[test]
+fn enum_variables_only_take_cases_of_their_enum() {
+    let source = format!("title: Start\n---\n{}<<declare $mood = Mood.Happy>>\n<<set $mood to \"Grumpy\">>\n===\n", MOOD);
+    let error = TestDialog::new(&source).try_next_event().unwrap_err();
+    assert_eq!(error.to_string(), "Variable $mood is declared as Mood, but String was assigned");
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mood {
+    Happy,
+    Grumpy,
+}
+
+impl YarnEnum for Mood {
+    const ENUM_NAME: &'static str = "Mood";
+
+    fn case_name(&self) -> &'static str {
+        match self {
+            Mood::Happy => "Happy",
+            Mood::Grumpy => "Grumpy",
+        }
+    }
+
+    fn from_case_name(case: &str) -> Option<Self> {
+        match case {
+            "Happy" => Some(Mood::Happy),
+            "Grumpy" => Some(Mood::Grumpy),
+            _ => None,
+        }
+    }
+}
+
+/// Stores `$mood` typed instead of as a `YarnValue`
+struct TypedState {
+    mood: Mood,
+}
+
+impl StateContext for TypedState {
+    fn get_value(&self, key: &str) -> Option<YarnValue> {
+        (key == "mood").then(|| YarnValue::from_enum(&self.mood))
+    }
+
+    fn set_value(&mut self, key: &str, value: &YarnValue) {
+        if key == "mood" {
+            self.mood = value.as_enum().unwrap();
+        }
+    }
+}
+
+#[test]
+fn typed_contexts_map_cases_to_rust_enums() {
+    let source = format!(
+        "title: Start\n---\n{}<<declare $mood = Mood.Happy>>\nWas {{$mood}}\n<<set $mood to Mood.Grumpy>>\n===\n",
+        MOOD
+    );
+    let dialog = load_from_file(&source, &Default::default()).unwrap();
+    let mut state = TypedState { mood: Mood::Grumpy };
+    let mut runner = DialogRunner::create_from_dialog(&dialog, "Start", &mut state).unwrap();
+    let world = World::new();
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, &world);
+
+    // the context already holds a value, so the declared default is not seeded
+    let DialogEvent::Dialog { text, .. } = runner.next_event(&mut state, &mut commands).unwrap() else {
+        panic!("expected a line");
+    };
+    assert_eq!(text, "Was Grumpy");
+    state.mood = Mood::Happy;
+    assert!(matches!(runner.next_event(&mut state, &mut commands).unwrap(), DialogEvent::End));
+    assert_eq!(state.mood, Mood::Grumpy);
+}
+
+#[test]
+fn as_enum_and_from_enum() {
+    let happy = YarnValue::Enum { enum_name: "Mood".to_string(), case: "Happy".to_string() };
+    assert_eq!(YarnValue::from_enum(&Mood::Happy), happy);
+    assert_eq!(happy.as_enum::<Mood>(), Ok(Mood::Happy));
+
+    let other_enum = YarnValue::Enum { enum_name: "Weather".to_string(), case: "Happy".to_string() };
+    let unknown_case = YarnValue::Enum { enum_name: "Mood".to_string(), case: "Sad".to_string() };
+    for value in [other_enum, unknown_case, YarnValue::from("Happy")] {
+        let error = value.as_enum::<Mood>().unwrap_err();
+        assert_eq!((error.value, error.target), (value, "Mood"));
+    }
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use bevy_yarnspinner::bevy_detective_derive::yarn_function;
+use bevy_yarnspinner::dialog_runner::components::DialogEvent;
+use bevy_yarnspinner::dialog_runner::dialog_runner_error::DialogRunnerError;
+use bevy_yarnspinner::dialog_runner::runner::FUNCTION_REGISTRY;
+use bevy_yarnspinner::parsing::value::YarnValue;
+use common::{first_event, TestDialog};
+
+fn line(source_line: &str) -> Result<String, DialogRunnerError> {
+    let source = format!("title: Start\n---\n{}\n===\n", source_line);
+    match TestDialog::new(&source).try_next_event()? {
+        DialogEvent::Dialog { text, .. } => Ok(text),
+        event => panic!("expected a line, got {:?}", event),
+    }
+}
+
+#[test]
+fn typed_arguments_are_converted() {
+    #[yarn_function]
+    fn describe(name: String, amount: f32, count: i32, loud: bool, extra: YarnValue) -> String {
+        format!("{} {} {} {} {:?}", name, amount, count, loud, extra)
+    }
+    #[yarn_function("has_item")]
+    fn has_item_in_inventory(name: String) -> bool {
+        name == "key"
+    }
+
+    assert_eq!(
+        line(r#"{describe("gem", 1.5, "3", 1, "x")}"#).unwrap(),
+        r#"gem 1.5 3 true String("x")"#
+    );
+    assert_eq!(line("{describe(2, true, 3.9, false, 4)}").unwrap(), "2 1 3 false Number(4.0)");
+    let DialogEvent::Dialog { text, .. } = first_event("title: Start\n---\nOpen <<if has_item(\"key\")>>\nLocked\n===\n") else {
+        panic!("expected a line");
+    };
+    assert_eq!(text, "Open");
+}
+
+#[test]
+fn wrong_arity_and_failed_conversions_are_errors() {
+    #[yarn_function]
+    fn double(number: f32) -> f32 {
+        number * 2.0
+    }
+
+    assert_eq!(line("{double(2)}").unwrap(), "4");
+    assert!(matches!(
+        line("{double(1, 2)}").unwrap_err(),
+        DialogRunnerError::FunctionArity { name, expected: 1, found: 2 } if name == "double"
+    ));
+    assert!(matches!(
+        line(r#"{double("lots")}"#).unwrap_err(),
+        DialogRunnerError::InvalidValue { value: YarnValue::String(text), expected: "Number" } if text == "lots"
+    ));
+}
+
+#[test]
+fn builtin_functions_shadow_registered_ones() {
+    #[yarn_function]
+    fn round(_number: f32) -> f32 {
+        42.0
+    }
+
+    assert_eq!(line("{round(1.4)}").unwrap(), "1");
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use bevy_yarnspinner::dialog_runner::components::DialogEvent;
+use bevy_yarnspinner::parsing::components::LineType;
+use bevy_yarnspinner::parsing::yarn_spinner_parsing::load_from_file;
+use common::{node_titles, play, TestDialog};
+
+#[test]
+fn windows_line_endings_and_byte_order_mark() {
+    let source = include_str!("corpus/crlf.yarn");
+    assert_eq!(
+        play(source),
+        ["Hello from Windows", "-> Wave", "-> Leave", "You waved", "Still here"]
+    );
+}
+
+#[test]
+fn tab_indentation() {
+    let source = include_str!("corpus/tabs.yarn");
+    assert_eq!(
+        play(source),
+        ["-> Ask", "-> Leave", "Answer", "Nested in tabs", "Done"]
+    );
+}
+
+#[test]
+fn comments() {
+    let source = include_str!("corpus/comments.yarn");
+    assert_eq!(node_titles(source), ["Start", "Other"]);
+    assert_eq!(
+        play(source),
+        ["Hello", "-> Option", "Option body", "Visited"]
+    );
+}
+
+#[test]
+fn blank_lines() {
+    let source = include_str!("corpus/blank_lines.yarn");
+    assert_eq!(node_titles(source), ["Start", "Other"]);
+    assert_eq!(
+        play(source),
+        ["First line", "-> Ask", "-> Leave", "Answer", "Inside the block", "Grumpy today", "Last line"]
+    );
+}
+
+#[test]
+fn narration_with_colons() {
+    let mut dialog = TestDialog::new(include_str!("corpus/narration.yarn"));
+    let mut lines = vec![];
+    while let DialogEvent::Dialog { speaker, text, .. } = dialog.next_event() {
+        lines.push((speaker, text));
+    }
+    let narration = |text: &str| (None, text.to_string());
+    assert_eq!(
+        lines,
+        [
+            narration("The door creaks open."),
+            narration("The ratio is 3:2"),
+            narration("10:30 is late."),
+            narration("Note: this is still narration"),
+            (Some("Mae".to_string()), "Hello: how are you?".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn missing_trailing_newline() {
+    let source = include_str!("corpus/no_trailing_newline.yarn");
+    assert_eq!(play(source), ["The file ends without a newline"]);
+}
+
+#[test]
+fn escaped_characters_and_line_continuations() {
+    let source = include_str!("corpus/escapes.yarn");
+    assert_eq!(
+        play(source),
+        [
+            "This is not a #tag",
+            "Neither is this a <<command>>",
+            "Braces {stay} braces",
+            "Markup [b]is literal[/b]",
+            "A backslash \\ and a slash // without a comment",
+            "This line is continued on the next line",
+        ]
+    );
+}
+
+#[test]
+fn unicode_speakers_and_titles() {
+    let source = include_str!("corpus/unicode.yarn");
+    assert_eq!(node_titles(source), ["Start", "Chapter1.Intro", "intro-scene"]);
+    assert_eq!(play(source), ["Hallo!", "Cześć", "こんにちは", "The end"]);
+}
+
+#[test]
+fn invalid_title() {
+    let source = "title: My Node\n---\nNarrator: Hello\n===\n";
+    let error = load_from_file(source, &Default::default()).unwrap_err();
+    assert!(error.to_string().starts_with("Invalid node title \"My Node\" on line 1"));
+}
+
+#[test]
+fn conditions_starting_with_literals_operators_and_calls() {
+    let source = include_str!("corpus/conditions.yarn");
+    let dialog = load_from_file(source, &Default::default()).unwrap();
+    let node = dialog.nodes[0].read().unwrap();
+
+    let LineType::OptionLine { possibilities, .. } = &node.lines[0] else {
+        panic!("expected options");
+    };
+    for (possibility, text) in possibilities.iter().zip(["A", "B", "C", "D"]) {
+        assert_eq!(possibility.text, text);
+        assert!(possibility.condition.is_some());
+    }
+
+    let LineType::DialogLine { text, commands, condition, .. } = &node.lines[1] else {
+        panic!("expected a dialog line");
+    };
+    assert_eq!(text, "hidden");
+    assert!(commands.is_empty());
+    assert!(condition.is_some());
+
+    let LineType::LineGroup { items } = &node.lines[2] else {
+        panic!("expected a line group");
+    };
+    assert!(items.iter().all(|item| item.condition.is_some()));
+}
@@ -0,0 +1,98 @@
+use bevy_yarnspinner::asset::asset::YarnSpinnerDialogLoaderSettings;
+use bevy_yarnspinner::parsing::components::LineType;
+use bevy_yarnspinner::parsing::value::YarnValueType;
+use bevy_yarnspinner::parsing::yarn_spinner_parsing::{load_from_file, load_from_named_file};
+
+#[test]
+fn node_headers() {
+    let source = include_str!("corpus/headers.yarn");
+    let dialog = load_from_file(source, &Default::default()).unwrap();
+    let start = dialog.nodes[0].read().unwrap();
+    assert_eq!(start.header("position"), Some("-120,40"));
+    assert_eq!(start.header("color"), Some("red"));
+    assert_eq!(start.tags, ["bark", "shop"]);
+    assert_eq!(dialog.nodes[1].read().unwrap().header("colorID"), Some("2"));
+
+    let tagged = |tag: &str| -> Vec<String> {
+        dialog.nodes_with_tag(tag).iter().map(|node| node.read().unwrap().title.clone()).collect()
+    };
+    assert_eq!(tagged("shop"), ["Start", "Other"]);
+    assert_eq!(tagged("bark"), ["Start"]);
+    assert!(tagged("quest").is_empty());
+}
+
+#[test]
+fn line_ids() {
+    let source = include_str!("corpus/line_ids.yarn");
+    let dialog = load_from_named_file(source, "line_ids", &Default::default()).unwrap();
+    let node = dialog.nodes[0].read().unwrap();
+    let mut ids = vec![];
+    for line in node.lines.iter() {
+        match line {
+            LineType::DialogLine { line_id, tags, .. } => {
+                assert!(tags.is_empty());
+                ids.push(line_id.clone());
+            }
+            LineType::OptionLine { possibilities, .. } => {
+                ids.extend(possibilities.iter().map(|possibility| possibility.line_id.clone()))
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(ids, ["line:guard_halt", "line:line_ids:Start:1", "line:friend", "line:line_ids:Start:3"]);
+}
+
+#[test]
+fn duplicate_line_ids() {
+    let source = "title: Start\n---\nA: One #line:same\nB: Two #line:same\n===\n";
+    let error = load_from_file(source, &Default::default()).unwrap_err();
+    assert_eq!(error.to_string(), "Line id used more than once: line:same");
+}
+
+#[test]
+fn default_option_speaker() {
+    let source = "title: Start\n---\n-> Mae: Ask\n-> Leave\n===\n";
+    let option_speakers = |settings: &YarnSpinnerDialogLoaderSettings| -> Vec<Option<String>> {
+        let dialog = load_from_file(source, settings).unwrap();
+        let node = dialog.nodes[0].read().unwrap();
+        let LineType::OptionLine { possibilities, .. } = &node.lines[0] else {
+            panic!("expected an option group");
+        };
+        possibilities.iter().map(|possibility| possibility.speaker.clone()).collect()
+    };
+    let speaker = |name: &str| Some(name.to_string());
+
+    assert_eq!(option_speakers(&Default::default()), [speaker("Mae"), speaker("Player")]);
+    let settings = YarnSpinnerDialogLoaderSettings { default_option_speaker: speaker("Hero") };
+    assert_eq!(option_speakers(&settings), [speaker("Mae"), speaker("Hero")]);
+    let settings = YarnSpinnerDialogLoaderSettings { default_option_speaker: None };
+    assert_eq!(option_speakers(&settings), [speaker("Mae"), None]);
+}
+
+#[test]
+fn declared_types_must_be_known() {
+    let source = "title: Start\n---\n<<declare $gold = 0 as Numbr>>\n===\n";
+    let error = load_from_file(source, &Default::default()).unwrap_err();
+    assert_eq!(error.to_string(), "Unknown type of $gold: Numbr");
+
+    // enums may be declared after the variables using them
+    let source = "title: Start\n---\n<<declare $mood = Mood.Happy as Mood>>\n<<enum Mood>>\n<<case Happy>>\n<<endenum>>\n===\n";
+    let dialog = load_from_file(source, &Default::default()).unwrap();
+    assert_eq!(dialog.declarations["mood"].value_type, YarnValueType::Enum("Mood".to_string()));
+
+    let source = "title: Start\n---\n<<declare $gold = \"none\" as Number>>\n===\n";
+    let error = load_from_file(source, &Default::default()).unwrap_err();
+    assert_eq!(error.to_string(), "Default value of $gold is String, but it is declared as Number");
+}
+
+#[test]
+fn invalid_markup_is_reported_when_loading() {
+    let error = load_from_file("title: Start\n---\nM: a] b\n===\n", &Default::default()).unwrap_err();
+    assert!(error.to_string().starts_with("Invalid markup at character 2 of \"a] b\""));
+
+    let error = load_from_file("title: Start\n---\n-> [b oops\n===\n", &Default::default()).unwrap_err();
+    assert!(error.to_string().starts_with("Invalid markup"));
+
+    // the markup of lines with interpolations is only known once they are shown
+    assert!(load_from_file("title: Start\n---\nM: {$x}]\n===\n", &Default::default()).is_ok());
+}
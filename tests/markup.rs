@@ -0,0 +1,102 @@
+use bevy_yarnspinner::parsing::markup::{parse_markup, MarkupParseResult, MarkupValue};
+
+fn parse(line: &str) -> MarkupParseResult {
+    parse_markup(line, None, "en").unwrap()
+}
+
+/// Name, position and length of every attribute
+fn ranges(result: &MarkupParseResult) -> Vec<(&str, usize, usize)> {
+    result
+        .attributes
+        .iter()
+        .map(|attribute| (attribute.name.as_str(), attribute.position, attribute.length))
+        .collect()
+}
+
+#[test]
+fn attribute_ranges() {
+    let result = parse("A [b]bold[/b] and [i]italic [u]underlined[/u][/i] word");
+    assert_eq!(result.text, "A bold and italic underlined word");
+    assert_eq!(ranges(&result), [("b", 2, 4), ("i", 11, 17), ("u", 18, 10)]);
+    assert_eq!(result.text_for(result.attribute("u").unwrap()), "underlined");
+}
+
+#[test]
+fn positions_count_characters() {
+    let result = parse("Grüße [b]für[/b] 村人");
+    assert_eq!(result.text, "Grüße für 村人");
+    assert_eq!(ranges(&result), [("b", 6, 3)]);
+}
+
+#[test]
+fn close_all_and_unclosed_tags() {
+    let result = parse("[b]one [i]two[/] three [wave]four");
+    assert_eq!(result.text, "one two three four");
+    assert_eq!(ranges(&result), [("b", 0, 7), ("i", 4, 3), ("wave", 14, 4)]);
+}
+
+#[test]
+fn properties() {
+    let result = parse(r#"[color=red]x[/color][fx n=1 f=1.5 on=true s="say \"hi\""]y[/fx]"#);
+    assert_eq!(result.text, "xy");
+    let color = result.attribute("color").unwrap();
+    assert_eq!(color.property("color"), Some(&MarkupValue::String("red".into())));
+    let fx = result.attribute("fx").unwrap();
+    assert_eq!(fx.property("n"), Some(&MarkupValue::Integer(1)));
+    assert_eq!(fx.property("f"), Some(&MarkupValue::Float(1.5)));
+    assert_eq!(fx.property("on"), Some(&MarkupValue::Bool(true)));
+    assert_eq!(fx.property("s"), Some(&MarkupValue::String("say \"hi\"".into())));
+}
+
+#[test]
+fn self_closing_tags_trim_one_following_space() {
+    let result = parse("Hello [pause/] world");
+    assert_eq!(result.text, "Hello world");
+    assert_eq!(ranges(&result), [("pause", 6, 0)]);
+
+    let result = parse("Hello [pause trimwhitespace=false/] world");
+    assert_eq!(result.text, "Hello  world");
+
+    // only after whitespace or at the start of the line
+    let result = parse("Hello[pause/] world");
+    assert_eq!(result.text, "Hello world");
+    assert_eq!(ranges(&result), [("pause", 5, 0)]);
+}
+
+#[test]
+fn escapes() {
+    let result = parse(r"\[not markup\] \\ \# \< \> \{ \} \/ \q");
+    assert_eq!(result.text, r"[not markup] \ # < > { } / \q");
+    assert!(result.attributes.is_empty());
+}
+
+#[test]
+fn nomarkup_sections_are_literal() {
+    let result = parse("a [nomarkup][b]raw\\[/b][/nomarkup] z");
+    assert_eq!(result.text, "a [b]raw\\[/b] z");
+    assert_eq!(ranges(&result), [("nomarkup", 2, 11)]);
+}
+
+#[test]
+fn character_name() {
+    let result = parse_markup("[b]Hi[/b] there [wave/]friend", Some("Mae"), "en").unwrap();
+    assert_eq!(result.text, "Mae: Hi there friend");
+    assert_eq!(ranges(&result), [("character", 0, 5), ("b", 5, 2), ("wave", 14, 0)]);
+    let character = result.attribute("character").unwrap();
+    assert_eq!(character.property("name"), Some(&MarkupValue::String("Mae".into())));
+
+    let line = result.without_character_name();
+    assert_eq!(line.text, "Hi there friend");
+    assert_eq!(ranges(&line), [("b", 0, 2), ("wave", 9, 0)]);
+}
+
+#[test]
+fn errors() {
+    let error = parse_markup("a] b", None, "en").unwrap_err();
+    assert_eq!(error.position, 2);
+    assert!(parse_markup("[/b]", None, "en").is_err());
+    assert!(parse_markup("[b", None, "en").is_err());
+    assert!(parse_markup("[b x=\"open]", None, "en").is_err());
+    assert!(parse_markup("[nomarkup]never closed", None, "en").is_err());
+    assert!(parse_markup("[]", None, "en").is_err());
+}
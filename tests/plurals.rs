@@ -0,0 +1,120 @@
+mod common;
+
+use bevy_yarnspinner::dialog_runner::components::DialogEvent;
+use bevy_yarnspinner::parsing::markup::parse_markup;
+use bevy_yarnspinner::parsing::plurals::{cardinal, ordinal, PluralCategory};
+use bevy_yarnspinner::parsing::value::YarnValue;
+use common::{Context, TestDialog};
+use PluralCategory::*;
+
+#[test]
+fn cardinal_categories() {
+    let table: &[(&str, f32, PluralCategory)] = &[
+        ("en", 0.0, Other),
+        ("en", 1.0, One),
+        ("en", 2.0, Other),
+        ("en", 1.5, Other),
+        ("de", 1.0, One),
+        ("de", 7.0, Other),
+        ("fr", 0.0, One),
+        ("fr", 1.0, One),
+        ("fr", 1.5, One),
+        ("fr", 2.0, Other),
+        ("fr", 1_000_000.0, Many),
+        ("pl", 1.0, One),
+        ("pl", 2.0, Few),
+        ("pl", 5.0, Many),
+        ("pl", 12.0, Many),
+        ("pl", 22.0, Few),
+        ("pl", 1.5, Other),
+        ("ru", 1.0, One),
+        ("ru", 11.0, Many),
+        ("ru", 21.0, One),
+        ("ru", 3.0, Few),
+        ("ru", 5.0, Many),
+        ("ru", 2.5, Other),
+        ("ja", 1.0, Other),
+        ("ja", 2.0, Other),
+    ];
+    for (locale, value, expected) in table {
+        assert_eq!(cardinal(locale, *value), *expected, "cardinal {} in {}", value, locale);
+    }
+}
+
+#[test]
+fn ordinal_categories() {
+    let table: &[(&str, f32, PluralCategory)] = &[
+        ("en", 1.0, One),
+        ("en", 2.0, Two),
+        ("en", 3.0, Few),
+        ("en", 4.0, Other),
+        ("en", 11.0, Other),
+        ("en", 12.0, Other),
+        ("en", 13.0, Other),
+        ("en", 21.0, One),
+        ("en", 22.0, Two),
+        ("en", 103.0, Few),
+        ("fr", 1.0, One),
+        ("fr", 2.0, Other),
+        ("de", 1.0, Other),
+        ("pl", 2.0, Other),
+        ("ru", 3.0, Other),
+        ("ja", 1.0, Other),
+    ];
+    for (locale, value, expected) in table {
+        assert_eq!(ordinal(locale, *value), *expected, "ordinal {} in {}", value, locale);
+    }
+}
+
+#[test]
+fn locale_regions_and_unknown_languages() {
+    assert_eq!(cardinal("pl-PL", 3.0), Few);
+    assert_eq!(cardinal("ru_RU", 21.0), One);
+    // unsupported languages use the English rules
+    assert_eq!(cardinal("pt_BR", 1.0), One);
+    assert_eq!(ordinal("nl", 2.0), Two);
+}
+
+fn replace(line: &str, locale: &str) -> String {
+    parse_markup(line, None, locale).unwrap().text
+}
+
+#[test]
+fn select_plural_and_ordinal_markers() {
+    let apples = r#"[plural value=3 one="% apple" other="% apples"/]"#;
+    assert_eq!(replace(apples, "en"), "3 apples");
+    assert_eq!(replace(&apples.replace('3', "1"), "en"), "1 apple");
+
+    let jablka = r#"[plural value=5 one="% jabłko" few="% jabłka" many="% jabłek" other="% jabłka"/]"#;
+    assert_eq!(replace(jablka, "pl"), "5 jabłek");
+    assert_eq!(replace(&jablka.replace('5', "22"), "pl"), "22 jabłka");
+
+    let place = r#"You came [ordinal value=22 one="%st" two="%nd" few="%rd" other="%th"/]!"#;
+    assert_eq!(replace(place, "en"), "You came 22nd!");
+    assert_eq!(replace(&place.replace("22", "13"), "en"), "You came 13th!");
+
+    let pronoun = r#"[select value=female male="he" female="she" other="they"/] waved"#;
+    assert_eq!(replace(pronoun, "en"), "she waved");
+    // a value without its own text uses `other`
+    assert_eq!(replace(&pronoun.replace("female male", "robot male"), "en"), "they waved");
+}
+
+#[test]
+fn invalid_markers() {
+    assert!(parse_markup(r#"[plural one="% cat"/]"#, None, "en").is_err());
+    assert!(parse_markup(r#"[plural value=cat one="% cat" other="% cats"/]"#, None, "en").is_err());
+    assert!(parse_markup(r#"[select value=x y="z"/]"#, None, "en").is_err());
+}
+
+#[test]
+fn markers_use_the_runner_locale_and_interpolated_values() {
+    let source = "title: Start\n---\nI have [plural value={$count} one=\"% kot\" few=\"% koty\" many=\"% kotów\"/].\n===\n";
+    let mut context = Context::new();
+    context.insert("count".to_string(), YarnValue::Number(3.0));
+    let mut dialog = TestDialog::with_context(source, context);
+    dialog.runner.set_locale("pl");
+    let DialogEvent::Dialog { text, .. } = dialog.next_event() else {
+        panic!("expected a line");
+    };
+    assert_eq!(text, "I have 3 koty.");
+}
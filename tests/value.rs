@@ -0,0 +1,107 @@
+use bevy_yarnspinner::parsing::value::YarnValue;
+
+fn number(value: f32) -> YarnValue {
+    YarnValue::Number(value)
+}
+
+fn string(text: &str) -> YarnValue {
+    YarnValue::String(text.to_string())
+}
+
+fn boolean(value: bool) -> YarnValue {
+    YarnValue::Bool(value)
+}
+
+fn mood(case: &str) -> YarnValue {
+    YarnValue::Enum { enum_name: "Mood".to_string(), case: case.to_string() }
+}
+
+#[test]
+fn as_number() {
+    let cases = [
+        (number(2.5), Some(2.5)),
+        (boolean(true), Some(1.0)),
+        (boolean(false), Some(0.0)),
+        (string("42"), Some(42.0)),
+        (string(" -1.5 "), Some(-1.5)),
+        (string("lots"), None),
+        (string(""), None),
+        (mood("Happy"), None),
+    ];
+    for (value, expected) in cases {
+        let converted = value.as_number();
+        assert_eq!(converted.as_ref().ok().copied(), expected, "{:?}", value);
+        if let Err(error) = converted {
+            assert_eq!((error.value, error.target), (value, "Number"));
+        }
+    }
+}
+
+#[test]
+fn as_bool() {
+    let cases = [
+        (boolean(true), Some(true)),
+        (number(0.0), Some(false)),
+        (number(-2.0), Some(true)),
+        (number(f32::NAN), Some(false)),
+        (string("True"), Some(true)),
+        (string(" false "), Some(false)),
+        (string("yes"), None),
+        (string("1"), None),
+        (mood("Happy"), None),
+    ];
+    for (value, expected) in cases {
+        let converted = value.as_bool();
+        assert_eq!(converted.as_ref().ok().copied(), expected, "{:?}", value);
+        if let Err(error) = converted {
+            assert_eq!((error.value, error.target), (value, "Bool"));
+        }
+    }
+}
+
+#[test]
+fn as_string() {
+    let cases = [
+        (number(3.0), "3"),
+        (number(-0.5), "-0.5"),
+        (boolean(true), "True"),
+        (boolean(false), "False"),
+        (string("text"), "text"),
+        (mood("Happy"), "Happy"),
+    ];
+    for (value, expected) in cases {
+        assert_eq!(value.as_string(), expected);
+    }
+}
+
+#[test]
+fn equality_converts_the_right_hand_side() {
+    let cases = [
+        (number(1.0), number(1.0), true),
+        (number(1.0), string("1"), true),
+        (number(1.0), string("one"), false),
+        (number(1.0), boolean(true), true),
+        (number(2.0), boolean(true), false),
+        (number(0.0), mood("Happy"), false),
+        (boolean(true), number(5.0), true),
+        (boolean(false), number(0.0), true),
+        (boolean(true), string("TRUE"), true),
+        (boolean(false), string("no"), false),
+        (string("1"), number(1.0), true),
+        (string("1.0"), number(1.0), false),
+        (string("True"), boolean(true), true),
+        (string("true"), boolean(true), false),
+        (string("Happy"), mood("Happy"), true),
+        (mood("Happy"), mood("Happy"), true),
+        (mood("Happy"), mood("Grumpy"), false),
+        (mood("Happy"), string("Happy"), false),
+        (
+            mood("Happy"),
+            YarnValue::Enum { enum_name: "Weather".to_string(), case: "Happy".to_string() },
+            false,
+        ),
+    ];
+    for (left, right, expected) in cases {
+        assert_eq!(left.equals(&right), expected, "{:?} == {:?}", left, right);
+    }
+}